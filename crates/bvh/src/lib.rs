@@ -8,8 +8,7 @@ use utility::Vec3;
 
 pub mod aabb;
 mod split;
-
-use std::f32::EPSILON;
+pub mod tlas;
 
 pub struct BoundingData {
     idx: usize,
@@ -18,30 +17,44 @@ pub struct BoundingData {
 }
 
 impl BoundingData {
-    pub fn from_prim<T: Aabound>(prim: &T, idx: usize) -> Self {
-        let bounds = prim.aabb();
+    pub fn new(bounds: Aabb, idx: usize) -> Self {
         Self {
             idx,
             bounds,
             centroid: bounds.centroid(),
         }
     }
+    pub fn from_prim<T: Aabound>(prim: &T, idx: usize) -> Self {
+        Self::new(prim.aabb(), idx)
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Bvh {
     nodes: Vec<Node>,
 }
 
 impl Bvh {
     pub fn new<T: Aabound>(primitives: &mut [T]) -> Self {
+        let bounds = primitives.iter().map(Aabound::aabb).collect::<Vec<_>>();
+        Self::new_with_bounds(primitives, &bounds)
+    }
+
+    // for primitives that need external data (e.g. a vertex buffer) to compute their bounds
+    pub fn new_with_bounds<T>(primitives: &mut [T], bounds: &[Aabb]) -> Self {
+        assert_eq!(primitives.len(), bounds.len());
+
         let mut bvh = Self { nodes: Vec::new() };
 
+        if primitives.is_empty() {
+            return bvh;
+        }
+
         // generate bounding data & index data for primitives
-        let mut prim_data = primitives
+        let mut prim_data = bounds
             .iter()
             .enumerate()
-            .map(|(i, p)| BoundingData::from_prim(p, i))
+            .map(|(i, b)| BoundingData::new(*b, i))
             .collect::<Vec<_>>();
 
         let mut order = Vec::new();
@@ -84,7 +97,8 @@ impl Bvh {
             // use the axis with the maximum extend to split with
             let max_axis = utility::max_axis(&centroid_bounds.extent());
 
-            if centroid_bounds.max[max_axis] - centroid_bounds.min[max_axis] < 100.0 * EPSILON {
+            if centroid_bounds.max[max_axis] - centroid_bounds.min[max_axis] < 100.0 * f32::EPSILON
+            {
                 // the maximum axis is small enough that it's not worth splitting
                 for idx in prim_data.iter().map(|v| v.idx) {
                     prim_order.push(idx)
@@ -114,6 +128,10 @@ impl Bvh {
     pub fn traverse(&self, ray: &Ray) -> Vec<Range<usize>> {
         let mut ranges = Vec::new();
 
+        if self.nodes.is_empty() {
+            return ranges;
+        }
+
         let mut node_stack = VecDeque::from([0]);
         while !node_stack.is_empty() {
             let idx = node_stack.pop_front().unwrap();
@@ -0,0 +1,87 @@
+use crate::aabb::Aabb;
+use crate::Bvh;
+use utility::Ray;
+
+// an object space primitive (usually with its own bottom level bvh) placed in the world
+pub trait Instance {
+    fn object_ray(&self, ray: &Ray) -> Ray;
+}
+
+#[derive(Debug)]
+pub struct InstanceHit {
+    pub instance: usize,
+    // ray in the instance's object space
+    pub ray: Ray,
+}
+
+// top level acceleration structure over instances of bottom level bvhs, unlike Bvh
+// this doesn't reorder the instances so their indices stay stable
+#[derive(Debug, Default)]
+pub struct Tlas {
+    bvh: Bvh,
+    order: Vec<usize>,
+}
+
+impl Tlas {
+    pub fn new(bounds: &[Aabb]) -> Self {
+        let mut order = (0..bounds.len()).collect::<Vec<_>>();
+        let bvh = Bvh::new_with_bounds(&mut order, bounds);
+        Self { bvh, order }
+    }
+
+    // instances whose world space bounds the ray intersects
+    pub fn traverse<T: Instance>(&self, instances: &[T], ray: &Ray) -> Vec<InstanceHit> {
+        self.bvh
+            .traverse(ray)
+            .into_iter()
+            .flat_map(|r| &self.order[r])
+            .map(|&idx| InstanceHit {
+                instance: idx,
+                ray: instances[idx].object_ray(ray),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aabb::Aabound;
+    use utility::Vec3;
+
+    struct Prim(Aabb);
+
+    impl Aabound for Prim {
+        fn aabb(&self) -> Aabb {
+            self.0
+        }
+    }
+
+    struct Translated(Vec3);
+
+    impl Instance for Translated {
+        fn object_ray(&self, ray: &Ray) -> Ray {
+            Ray::new(ray.origin - self.0, ray.dir)
+        }
+    }
+
+    #[test]
+    fn traverse_instances() {
+        let unit = Aabb::new(-Vec3::repeat(1.0), Vec3::repeat(1.0));
+        let blas = Bvh::new(&mut [Prim(unit)]);
+
+        let offsets = [Vec3::zeros(), Vec3::new(5.0, 0.0, 0.0)];
+        let instances = offsets.map(Translated);
+        let bounds = offsets.map(|o| Aabb::new(unit.min + o, unit.max + o));
+        let tlas = Tlas::new(&bounds);
+
+        let ray = Ray::new(Vec3::new(5.0, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0));
+        let hits = tlas.traverse(&instances, &ray);
+
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].instance, 1);
+        assert_eq!(hits[0].ray.origin, Vec3::new(0.0, 0.0, -10.0));
+        // the object space ray finds the instance's blas
+        assert_eq!(blas.traverse(&hits[0].ray), vec![0..1]);
+    }
+}
@@ -8,14 +8,21 @@ pub struct Ray {
     pub origin: Vec3,
     pub dir: Vec3,
     pub inv_dir: Vec3,
+    // within the shutter interval, 0 at shutter open
+    pub time: f32,
 }
 
 impl Ray {
     pub fn new(origin: Vec3, dir: Vec3) -> Self {
+        Self::new_with_time(origin, dir, 0.0)
+    }
+
+    pub fn new_with_time(origin: Vec3, dir: Vec3, time: f32) -> Self {
         Self {
             origin,
             dir,
             inv_dir: Vec3::new(1.0 / dir.x, 1.0 / dir.y, 1.0 / dir.z),
+            time,
         }
     }
 }
@@ -146,6 +153,17 @@ pub fn reflect_across_normal(val: Vec3, normal: Vec3) -> Vec3 {
     2.0 * val.dot(&normal) * normal - val
 }
 
+// orthonormal basis around a unit vector (Duff et al. 2017)
+pub fn onb(n: Vec3) -> (Vec3, Vec3) {
+    let sign = 1.0f32.copysign(n.z);
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+    (
+        Vec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+        Vec3::new(b, sign + n.y * n.y * a, -n.y),
+    )
+}
+
 #[cfg(test)]
 mod tests {
 
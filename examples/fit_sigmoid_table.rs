@@ -0,0 +1,158 @@
+// fits the rgb to sigmoid coefficient table shipped as src/sigmoid_table.bin (jakob & hanika
+// 2019), run with cargo run --release --example fit_sigmoid_table
+use nalgebra::{Matrix3, Vector3};
+use pathtracer::{
+    colour::{x_bar, xyz_to_rgb, y_bar, z_bar},
+    material::{D65, MIN_WAVELENGTH, WAVELENGTH_RANGE},
+    rgb_spectrum::{z_node, SAMPLES, TABLE_RES},
+    spectrum::Spectrum,
+    Vec3,
+};
+use rayon::prelude::*;
+
+// cie weights of each wavelength under d65, normalised so a reflectance of 1 has a Y of 1
+struct FitData {
+    // normalised wavelength & xyz weight
+    weights: Vec<(f64, Vector3<f64>)>,
+    white: Vector3<f64>,
+    rgb_to_xyz: Matrix3<f64>,
+}
+
+impl FitData {
+    fn new() -> Self {
+        let d65 = Spectrum::from_bins(&D65);
+        let dx = WAVELENGTH_RANGE as f64 / SAMPLES as f64;
+        let mut weights = (0..=SAMPLES)
+            .map(|i| {
+                let t = i as f64 / SAMPLES as f64;
+                let wl = MIN_WAVELENGTH + t as f32 * WAVELENGTH_RANGE;
+                // trapezoid rule
+                let w = if i == 0 || i == SAMPLES { 0.5 * dx } else { dx };
+                let cmf = Vector3::new(x_bar(wl), y_bar(wl), z_bar(wl)).cast::<f64>();
+                (t, cmf * d65.eval(wl) as f64 * w)
+            })
+            .collect::<Vec<_>>();
+        let y: f64 = weights.iter().map(|(_, w)| w.y).sum();
+        for (_, w) in &mut weights {
+            *w /= y;
+        }
+        let white = weights.iter().map(|(_, w)| w).sum();
+
+        let xyz_to_rgb = Matrix3::from_columns(&[Vec3::x(), Vec3::y(), Vec3::z()].map(xyz_to_rgb));
+        Self {
+            weights,
+            white,
+            rgb_to_xyz: xyz_to_rgb.cast::<f64>().try_inverse().unwrap(),
+        }
+    }
+
+    // cielab difference between the sigmoid's colour & the target's
+    fn residual(&self, c: &Vector3<f64>, target_lab: &Vector3<f64>) -> Vector3<f64> {
+        let xyz: Vector3<f64> = self
+            .weights
+            .iter()
+            .map(|(t, w)| {
+                let x = (c.x * t + c.y) * t + c.z;
+                let s = 0.5 + x / (2.0 * (1.0 + x * x).sqrt());
+                w * s
+            })
+            .sum();
+        lab(xyz, self.white) - target_lab
+    }
+
+    // gauss-newton from the guess
+    fn fit(&self, rgb: Vector3<f64>, guess: Vector3<f64>) -> Vector3<f64> {
+        let target = lab(self.rgb_to_xyz * rgb, self.white);
+        let mut c = guess;
+        for _ in 0..15 {
+            let r = self.residual(&c, &target);
+            if r.norm() < 1e-6 {
+                break;
+            }
+            let mut jacobian = Matrix3::zeros();
+            for i in 0..3 {
+                let eps = 1e-5;
+                let (mut lo, mut hi) = (c, c);
+                lo[i] -= eps;
+                hi[i] += eps;
+                let column =
+                    (self.residual(&hi, &target) - self.residual(&lo, &target)) / (2.0 * eps);
+                jacobian.set_column(i, &column);
+            }
+            let Some(step) = jacobian.lu().solve(&r) else {
+                break;
+            };
+            c -= step;
+
+            // steep sigmoids don't change the colour, keep them from overflowing
+            let max = c.amax();
+            if max > 200.0 {
+                c *= 200.0 / max;
+            }
+        }
+        c
+    }
+}
+
+fn lab(xyz: Vector3<f64>, white: Vector3<f64>) -> Vector3<f64> {
+    let f = |t: f64| {
+        const DELTA: f64 = 6.0 / 29.0;
+        if t > DELTA.powi(3) {
+            t.cbrt()
+        } else {
+            t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+        }
+    };
+    let (x, y, z) = (f(xyz.x / white.x), f(xyz.y / white.y), f(xyz.z / white.z));
+    Vector3::new(116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z))
+}
+
+fn main() {
+    let res = TABLE_RES;
+    let fit = FitData::new();
+
+    // each column walks away from a moderate brightness, starting from the last fit
+    let columns = (0..3 * res * res)
+        .into_par_iter()
+        .map(|column| {
+            let (channel, y, x) = (column / (res * res), column / res % res, column % res);
+            let (x, y) = (x as f64 / (res - 1) as f64, y as f64 / (res - 1) as f64);
+            let solve = |z: usize, guess| {
+                let z = z_node(z) as f64;
+                let mut rgb = Vector3::zeros();
+                rgb[channel] = z;
+                rgb[(channel + 1) % 3] = x * z;
+                rgb[(channel + 2) % 3] = y * z;
+                fit.fit(rgb, guess)
+            };
+            let start = res / 5;
+            let mut coefficients = vec![Vector3::zeros(); res];
+            let mut guess = Vector3::zeros();
+            for (z, c) in coefficients.iter_mut().enumerate().skip(start) {
+                guess = solve(z, guess);
+                *c = guess;
+            }
+            let mut guess = coefficients[start];
+            for z in (0..start).rev() {
+                guess = solve(z, guess);
+                coefficients[z] = guess;
+            }
+            coefficients
+        })
+        .collect::<Vec<_>>();
+
+    let mut table = vec![0.0f32; 3 * res * res * res * 3];
+    for (column, values) in columns.into_iter().enumerate() {
+        let (channel, yx) = (column / (res * res), column % (res * res));
+        for (z, c) in values.into_iter().enumerate() {
+            let i = ((channel * res + z) * res * res + yx) * 3;
+            table[i..i + 3].copy_from_slice(c.cast::<f32>().as_slice());
+        }
+    }
+    let bytes = table
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect::<Vec<_>>();
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/src/sigmoid_table.bin");
+    std::fs::write(path, bytes).unwrap();
+}
@@ -0,0 +1,120 @@
+use crate::prelude::*;
+use serde::Deserialize;
+use std::ops::{Add, Mul, Sub};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    // catmull-rom style hermite spline through every key
+    Spline,
+}
+
+// values keyed at increasing times in seconds, held before the first & after the last key
+#[derive(Debug, Clone)]
+pub struct Track<T> {
+    times: Vec<f32>,
+    values: Vec<T>,
+    interpolation: Interpolation,
+}
+
+impl<T> Track<T>
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>,
+{
+    // None without keys or when the times aren't strictly increasing
+    pub fn new(keys: Vec<(f32, T)>, interpolation: Interpolation) -> Option<Self> {
+        if keys.is_empty() || keys.windows(2).any(|w| w[0].0 >= w[1].0) {
+            return None;
+        }
+        let (times, values) = keys.into_iter().unzip();
+        Some(Self {
+            times,
+            values,
+            interpolation,
+        })
+    }
+
+    pub fn at(&self, time: f32) -> T {
+        let last = self.times.len() - 1;
+        if time <= self.times[0] {
+            return self.values[0];
+        }
+        if time >= self.times[last] {
+            return self.values[last];
+        }
+
+        let i = self.times.partition_point(|&t| t <= time) - 1;
+        let h = self.times[i + 1] - self.times[i];
+        let s = (time - self.times[i]) / h;
+        let (p0, p1) = (self.values[i], self.values[i + 1]);
+
+        match self.interpolation {
+            Interpolation::Linear => p0 * (1.0 - s) + p1 * s,
+            Interpolation::Spline => {
+                let (m0, m1) = (self.tangent(i) * h, self.tangent(i + 1) * h);
+                let (s2, s3) = (s * s, s * s * s);
+                p0 * (2.0 * s3 - 3.0 * s2 + 1.0)
+                    + m0 * (s3 - 2.0 * s2 + s)
+                    + p1 * (-2.0 * s3 + 3.0 * s2)
+                    + m1 * (s3 - s2)
+            }
+        }
+    }
+
+    // rate of change per second at key i, one sided at the ends
+    fn tangent(&self, i: usize) -> T {
+        let (a, b) = (i.saturating_sub(1), (i + 1).min(self.times.len() - 1));
+        (self.values[b] - self.values[a]) * (1.0 / (self.times[b] - self.times[a]))
+    }
+}
+
+// scale, rotation in degrees around x, y then z & offset, composed like the scene file's
+#[derive(Debug, Clone)]
+pub struct TransformTrack {
+    pub scale: Track<Vec3>,
+    pub rotate: Track<Vec3>,
+    pub offset: Track<Vec3>,
+}
+
+impl TransformTrack {
+    pub fn at(&self, time: f32) -> Option<Transform> {
+        Transform::from_parts(
+            self.scale.at(time),
+            self.rotate.at(time),
+            self.offset.at(time),
+        )
+    }
+}
+
+// keyframes replacing an instance's transform
+#[derive(Debug, Clone)]
+pub struct Animation {
+    pub instance: usize,
+    pub track: TransformTrack,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolate_tracks() {
+        let keys = vec![(0.0, 0.0), (1.0, 1.0), (2.0, 0.0)];
+        let linear = Track::new(keys.clone(), Interpolation::Linear).unwrap();
+        assert_eq!(linear.at(-1.0), 0.0);
+        assert_eq!(linear.at(0.5), 0.5);
+        assert_eq!(linear.at(1.5), 0.5);
+        assert_eq!(linear.at(3.0), 0.0);
+
+        // passes through the keys, flat at the peak & overshooting the linear ramp
+        let spline = Track::new(keys, Interpolation::Spline).unwrap();
+        assert_eq!(spline.at(1.0), 1.0);
+        assert!((spline.at(0.999) - 1.0).abs() < 1e-3);
+        assert!(spline.at(0.75) > linear.at(0.75));
+
+        assert!(Track::<f32>::new(vec![(1.0, 0.0), (1.0, 1.0)], Interpolation::Linear).is_none());
+        assert!(Track::<f32>::new(Vec::new(), Interpolation::Linear).is_none());
+    }
+}
@@ -0,0 +1,182 @@
+use crate::prelude::*;
+use image::{
+    error::{ParameterError, ParameterErrorKind},
+    ImageError, ImageResult,
+};
+use std::{
+    f32::consts::{FRAC_PI_4, TAU},
+    path::Path,
+    sync::Arc,
+};
+
+// shape of the lens opening, samples are scaled by the aperture radius
+#[derive(Debug, Clone, Default)]
+pub enum Aperture {
+    #[default]
+    Disk,
+    // regular polygon inscribed in the unit circle, rotation in radians
+    Polygon {
+        blades: u32,
+        rotation: f32,
+    },
+    Mask(Arc<ApertureMask>),
+}
+
+impl Aperture {
+    // maps a uniform sample in [0, 1)^2 to a point in the unit disk
+    pub fn sample(&self, sample: Vec2) -> Vec2 {
+        match self {
+            Aperture::Disk => concentric_disk(sample),
+            Aperture::Polygon { blades, rotation } => {
+                // every blade is an equal area triangle with the centre
+                let x = sample.x * *blades as f32;
+                let blade = x.floor().min(*blades as f32 - 1.0);
+                let (su, sv) = ((x - blade).sqrt(), sample.y);
+
+                let angle = TAU / *blades as f32;
+                let a = blade * angle + rotation;
+                let b = a + angle;
+                let a = Vec2::new(a.cos(), a.sin());
+                let b = Vec2::new(b.cos(), b.sin());
+                su * (1.0 - sv) * a + su * sv * b
+            }
+            Aperture::Mask(mask) => mask.sample(sample),
+        }
+    }
+}
+
+// image whose luminance weights where rays leave the lens
+#[derive(Debug)]
+pub struct ApertureMask {
+    width: usize,
+    height: usize,
+    // running totals normalised to end at 1, per row & over the rows
+    rows: Vec<f32>,
+    columns: Vec<f32>,
+}
+
+impl ApertureMask {
+    // row major from the top, None if every weight is zero
+    pub fn new(width: usize, height: usize, weights: &[f32]) -> Option<Self> {
+        assert_eq!(width * height, weights.len());
+
+        let mut rows = Vec::with_capacity(height);
+        let mut columns = Vec::with_capacity(weights.len());
+        let mut total = 0.0;
+        for row in weights.chunks(width) {
+            let start = columns.len();
+            let mut sum = 0.0;
+            for w in row {
+                sum += w.max(0.0);
+                columns.push(sum);
+            }
+            if sum > 0.0 {
+                columns[start..].iter_mut().for_each(|c| *c /= sum);
+            }
+            total += sum;
+            rows.push(total);
+        }
+        if total <= 0.0 {
+            return None;
+        }
+        rows.iter_mut().for_each(|r| *r /= total);
+
+        Some(Self {
+            width,
+            height,
+            rows,
+            columns,
+        })
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> ImageResult<Self> {
+        let texture = Texture::open(path)?;
+        let (width, height) = texture.dimensions();
+        let weights = texture
+            .texels()
+            .iter()
+            .map(|t| t.dot(&Vec3::new(0.2126, 0.7152, 0.0722)))
+            .collect::<Vec<_>>();
+
+        Self::new(width, height, &weights).ok_or_else(|| {
+            ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+                "aperture mask is completely black".into(),
+            )))
+        })
+    }
+
+    // proportional to the weights, the longer side of the image spans [-1, 1]
+    pub fn sample(&self, sample: Vec2) -> Vec2 {
+        let (y, ty) = sample_cdf(&self.rows, sample.y);
+        let row = &self.columns[y * self.width..(y + 1) * self.width];
+        let (x, tx) = sample_cdf(row, sample.x);
+
+        let size = self.width.max(self.height) as f32;
+        Vec2::new(
+            (2.0 * (x as f32 + tx) - self.width as f32) / size,
+            (self.height as f32 - 2.0 * (y as f32 + ty)) / size,
+        )
+    }
+}
+
+// index of the bucket containing u & how far through it u is
+fn sample_cdf(cdf: &[f32], u: f32) -> (usize, f32) {
+    let i = cdf.partition_point(|&c| c <= u).min(cdf.len() - 1);
+    let start = if i == 0 { 0.0 } else { cdf[i - 1] };
+    let t = (u - start) / (cdf[i] - start);
+    (i, t.clamp(0.0, 1.0))
+}
+
+// shirley & chiu's mapping from the unit square to the unit disk
+pub fn concentric_disk(sample: Vec2) -> Vec2 {
+    let offset = 2.0 * sample - Vec2::repeat(1.0);
+    if offset.x == 0.0 && offset.y == 0.0 {
+        return Vec2::zeros();
+    }
+
+    let (r, theta) = if offset.x.abs() > offset.y.abs() {
+        (offset.x, FRAC_PI_4 * (offset.y / offset.x))
+    } else {
+        (
+            offset.y,
+            2.0 * FRAC_PI_4 - FRAC_PI_4 * (offset.x / offset.y),
+        )
+    };
+    r * Vec2::new(theta.cos(), theta.sin())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aperture_shapes() {
+        let hexagon = Aperture::Polygon {
+            blades: 6,
+            rotation: 0.0,
+        };
+        // the apothem of a hexagon in the unit circle
+        let inner = 3f32.sqrt() / 2.0;
+        for i in 0..64 {
+            let sample = Vec2::new((i % 8) as f32 / 8.0, (i / 8) as f32 / 8.0 + 0.01);
+            let p = hexagon.sample(sample);
+            let angle = p.y.atan2(p.x).rem_euclid(TAU / 6.0) - TAU / 12.0;
+            assert!(p.magnitude() * angle.cos() <= inner + 1e-5);
+        }
+
+        // only the bottom right texel of a 2x2 mask lets light through
+        let mask = ApertureMask::new(2, 2, &[0.0, 0.0, 0.0, 1.0]).unwrap();
+        for sample in [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(0.5, 0.5),
+            Vec2::new(0.99, 0.3),
+        ] {
+            let p = mask.sample(sample);
+            assert!((0.0..=1.0).contains(&p.x) && (-1.0..=0.0).contains(&p.y));
+        }
+        assert!(ApertureMask::new(1, 1, &[0.0]).is_none());
+
+        let sample = concentric_disk(Vec2::new(1.0, 0.5));
+        assert!((sample - Vec2::new(1.0, 0.0)).magnitude() < 1e-6);
+    }
+}
@@ -1,44 +1,359 @@
-use crate::prelude::*;
+use crate::{
+    aperture::Aperture,
+    prelude::*,
+    transform::{AnimatedTransform, Mat4, Transform},
+};
+use derive_new::new;
+use serde::Deserialize;
+use std::{
+    f32::consts::{PI, TAU},
+    str::FromStr,
+};
 
-pub struct Camera {
+#[derive(Debug, Clone, Copy, new)]
+pub struct CameraSample {
+    // u & v in [0, 1] from the top left of the image
+    pub film: Vec2,
+    // uniform in [0, 1)^2
+    pub lens: Vec2,
+    // in [0, 1] between the transforms at shutter open & close
+    pub time: f32,
+    // nm
+    pub wavelength: f32,
+}
+
+pub trait Camera: Sync {
+    // None where the projection doesn't cover the image, e.g. outside a fisheye's circle
+    fn get_ray(&self, sample: &CameraSample) -> Option<Ray>;
+
+    // whether rays of different wavelengths through the same sample leave in different directions
+    fn dispersive(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Projection {
+    #[default]
+    Perspective,
+    Orthographic,
+    Equirectangular,
+    Fisheye,
+}
+
+impl FromStr for Projection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "perspective" => Ok(Self::Perspective),
+            "orthographic" => Ok(Self::Orthographic),
+            "equirectangular" => Ok(Self::Equirectangular),
+            "fisheye" => Ok(Self::Fisheye),
+            _ => Err(format!(
+                "unknown projection \"{s}\", expected one of perspective, orthographic, equirectangular, fisheye"
+            )),
+        }
+    }
+}
+
+// orthonormal forward, right & up
+pub fn basis(origin: Vec3, look_at: Vec3, up: Vec3) -> (Vec3, Vec3, Vec3) {
+    let forward = (look_at - origin).normalize();
+    let right = forward.cross(&up).normalize();
+    (forward, right, right.cross(&forward))
+}
+
+pub struct Perspective {
     pub lower_left: Vec3,
     pub up: Vec3,
     pub right: Vec3,
     pub origin: Vec3,
+    // lens axes scaled by the aperture radius
+    lens_right: Vec3,
+    lens_up: Vec3,
+    aperture: Aperture,
 }
 
-impl Camera {
+impl Perspective {
+    // a pinhole when aperture is 0, otherwise a thin lens of that radius focused at focus_dist
     pub fn new(
         origin: Vec3,
         look_at: Vec3,
         up: Vec3,
         hfov: f32,
         focus_dist: f32,
+        aperture: f32,
         aspect_ratio: f32,
     ) -> Self {
-        let forward = (look_at - origin).normalize();
-        let up = up.normalize();
+        let (forward, right, up) = basis(origin, look_at, up);
 
         let right_mag = focus_dist * 2.0 * (0.5 * hfov.to_radians()).tan();
         let up_mag = right_mag / aspect_ratio;
 
-        let right = forward.cross(&up) * right_mag;
+        let lens_right = right * aperture;
+        let lens_up = up * aperture;
+
+        let right = right * right_mag;
         let up = up * up_mag;
 
         let lower_left = origin - 0.5 * right - 0.5 * up + forward * focus_dist;
 
-        Camera {
+        Perspective {
             origin,
             lower_left,
             right,
             up,
+            lens_right,
+            lens_up,
+            aperture: Aperture::Disk,
+        }
+    }
+
+    pub fn with_aperture(mut self, aperture: Aperture) -> Self {
+        self.aperture = aperture;
+        self
+    }
+
+    // aperture radius for an f-number, assuming scene units are metres and a 36mm wide sensor
+    pub fn aperture_radius(hfov: f32, f_stop: f32) -> f32 {
+        let focal_length = 0.018 / (0.5 * hfov.to_radians()).tan();
+        0.5 * focal_length / f_stop
+    }
+}
+
+impl Camera for Perspective {
+    fn get_ray(&self, sample: &CameraSample) -> Option<Ray> {
+        let (u, v) = (sample.film.x, sample.film.y);
+        let lens = self.aperture.sample(sample.lens);
+        let origin = self.origin + self.lens_right * lens.x + self.lens_up * lens.y;
+        Some(Ray::new_with_time(
+            origin,
+            self.lower_left + self.right * u + self.up * (1.0 - v) - origin,
+            sample.time,
+        ))
+    }
+}
+
+// parallel rays through a width wide window centred on origin
+pub struct Orthographic {
+    lower_left: Vec3,
+    right: Vec3,
+    up: Vec3,
+    forward: Vec3,
+}
+
+impl Orthographic {
+    pub fn new(origin: Vec3, look_at: Vec3, up: Vec3, width: f32, aspect_ratio: f32) -> Self {
+        let (forward, right, up) = basis(origin, look_at, up);
+        let right = right * width;
+        let up = up * width / aspect_ratio;
+
+        Orthographic {
+            lower_left: origin - 0.5 * right - 0.5 * up,
+            right,
+            up,
+            forward,
+        }
+    }
+}
+
+impl Camera for Orthographic {
+    fn get_ray(&self, sample: &CameraSample) -> Option<Ray> {
+        let (u, v) = (sample.film.x, sample.film.y);
+        Some(Ray::new_with_time(
+            self.lower_left + self.right * u + self.up * (1.0 - v),
+            self.forward,
+            sample.time,
+        ))
+    }
+}
+
+// full sphere with longitude across the image & look_at in the centre, best at a 2:1 aspect ratio
+pub struct Equirectangular {
+    origin: Vec3,
+    forward: Vec3,
+    right: Vec3,
+    up: Vec3,
+}
+
+impl Equirectangular {
+    pub fn new(origin: Vec3, look_at: Vec3, up: Vec3) -> Self {
+        let (forward, right, up) = basis(origin, look_at, up);
+        Equirectangular {
+            origin,
+            forward,
+            right,
+            up,
+        }
+    }
+}
+
+impl Camera for Equirectangular {
+    fn get_ray(&self, sample: &CameraSample) -> Option<Ray> {
+        let (u, v) = (sample.film.x, sample.film.y);
+        let phi = (u - 0.5) * TAU;
+        let theta = v * PI;
+        let dir = theta.sin() * (phi.cos() * self.forward + phi.sin() * self.right)
+            + theta.cos() * self.up;
+        Some(Ray::new_with_time(self.origin, dir, sample.time))
+    }
+}
+
+// equisolid angle fisheye, hfov is measured across the image width & can be up to 360 degrees
+pub struct Fisheye {
+    origin: Vec3,
+    forward: Vec3,
+    right: Vec3,
+    up: Vec3,
+    // image plane radius at the edge of the image width
+    radius: f32,
+    aspect_ratio: f32,
+}
+
+impl Fisheye {
+    pub fn new(origin: Vec3, look_at: Vec3, up: Vec3, hfov: f32, aspect_ratio: f32) -> Self {
+        let (forward, right, up) = basis(origin, look_at, up);
+        Fisheye {
+            origin,
+            forward,
+            right,
+            up,
+            radius: 2.0 * (0.25 * hfov.min(360.0).to_radians()).sin(),
+            aspect_ratio,
         }
     }
+}
+
+impl Camera for Fisheye {
+    fn get_ray(&self, sample: &CameraSample) -> Option<Ray> {
+        let (u, v) = (sample.film.x, sample.film.y);
+        let p = Vec2::new(2.0 * u - 1.0, (1.0 - 2.0 * v) / self.aspect_ratio);
+        // r = 2 sin(theta / 2) with a unit focal length
+        let r = p.magnitude() * self.radius;
+        if r > 2.0 {
+            return None;
+        }
+        let theta = 2.0 * (0.5 * r).asin();
+
+        let p = if r > 0.0 { p.normalize() } else { p };
+        let dir = theta.cos() * self.forward + theta.sin() * (p.x * self.right + p.y * self.up);
+        Some(Ray::new_with_time(self.origin, dir, sample.time))
+    }
+}
+
+// moves any camera from one frame at shutter open to another at shutter close
+pub struct MovingCamera {
+    camera: Box<dyn Camera>,
+    // world to the camera's frame at time 0
+    to_start: Transform,
+    motion: AnimatedTransform,
+}
+
+impl MovingCamera {
+    // camera is set up at the start frame
+    pub fn new(camera: Box<dyn Camera>, start: [Vec3; 3], end: [Vec3; 3]) -> Self {
+        let start = frame(start);
+        Self {
+            camera,
+            to_start: start.inverse(),
+            // frames are rotations, never mirrored
+            motion: AnimatedTransform::new(start, frame(end)).unwrap(),
+        }
+    }
+}
+
+// camera to world for an origin, look_at & up
+fn frame([origin, look_at, up]: [Vec3; 3]) -> Transform {
+    let (forward, right, up) = basis(origin, look_at, up);
+    let mut m = Mat4::identity();
+    for (i, axis) in [right, up, forward, origin].iter().enumerate() {
+        m.fixed_view_mut::<3, 1>(0, i).copy_from(axis);
+    }
+    Transform::new(m).unwrap()
+}
+
+impl Camera for MovingCamera {
+    fn get_ray(&self, sample: &CameraSample) -> Option<Ray> {
+        let ray = self.camera.get_ray(sample)?;
+        Some(self.motion.at(sample.time).ray(&self.to_start.ray(&ray)))
+    }
+
+    fn dispersive(&self) -> bool {
+        self.camera.dispersive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thin_lens_focus() {
+        let cam = Perspective::new(Vec3::zeros(), -Vec3::z(), Vec3::y(), 90.0, 2.0, 0.1, 1.0);
+        // every lens sample for a pixel meets at the same point on the focus plane
+        let focus = Vec3::new(-1.0, 1.0, -2.0);
+        for lens in [
+            Vec2::new(0.1, 0.9),
+            Vec2::new(0.5, 0.5),
+            Vec2::new(0.8, 0.3),
+        ] {
+            let ray = cam
+                .get_ray(&CameraSample::new(Vec2::new(0.25, 0.25), lens, 0.0, 550.0))
+                .unwrap();
+            assert!((ray.origin + ray.dir - focus).magnitude() < 1e-5);
+            assert!(ray.origin.z == 0.0 && ray.origin.magnitude() <= 0.1 + 1e-6);
+        }
+
+        assert!((Perspective::aperture_radius(90.0, 1.8) - 0.005).abs() < 1e-6);
+    }
+
+    #[test]
+    fn projections() {
+        let (origin, look_at) = (Vec3::zeros(), Vec3::y());
+        let at = |u, v| CameraSample::new(Vec2::new(u, v), Vec2::zeros(), 0.0, 550.0);
+        let close = |ray: Option<Ray>, dir: Vec3| {
+            let ray = ray.unwrap();
+            assert!(
+                (ray.dir.normalize() - dir).magnitude() < 1e-5,
+                "{:?}",
+                ray.dir
+            );
+        };
+
+        let ortho = Orthographic::new(origin, look_at, Vec3::z(), 2.0, 2.0);
+        let ray = ortho.get_ray(&at(1.0, 0.0)).unwrap();
+        assert!((ray.origin - Vec3::new(1.0, 0.0, 0.5)).magnitude() < 1e-6);
+        close(Some(ray), Vec3::y());
+
+        let equirect = Equirectangular::new(origin, look_at, Vec3::z());
+        close(equirect.get_ray(&at(0.5, 0.5)), Vec3::y());
+        close(equirect.get_ray(&at(0.75, 0.5)), Vec3::x());
+        close(equirect.get_ray(&at(0.0, 0.5)), -Vec3::y());
+        close(equirect.get_ray(&at(0.3, 0.0)), Vec3::z());
+
+        let fisheye = Fisheye::new(origin, look_at, Vec3::z(), 180.0, 1.0);
+        close(fisheye.get_ray(&at(0.5, 0.5)), Vec3::y());
+        close(fisheye.get_ray(&at(1.0, 0.5)), Vec3::x());
+        close(fisheye.get_ray(&at(0.5, 0.0)), Vec3::z());
+        let fisheye = Fisheye::new(origin, look_at, Vec3::z(), 360.0, 1.0);
+        close(fisheye.get_ray(&at(0.0, 0.5)), -Vec3::y());
+        assert!(fisheye.get_ray(&at(0.0, 0.0)).is_none());
 
-    pub fn get_ray(&self, u: f32, v: f32) -> Ray {
-        Ray::new(
-            self.origin,
-            self.lower_left + self.right * u + self.up * (1.0 - v) - self.origin,
-        )
+        // turns to the right & moves forward over the shutter interval
+        let up = Vec3::z();
+        let camera = Box::new(Equirectangular::new(origin, look_at, up));
+        let moving = MovingCamera::new(
+            camera,
+            [origin, look_at, up],
+            [look_at, Vec3::x() + look_at, up],
+        );
+        let mut sample = at(0.5, 0.5);
+        sample.time = 0.5;
+        let ray = moving.get_ray(&sample).unwrap();
+        assert_eq!(ray.time, 0.5);
+        assert!((ray.origin - 0.5 * Vec3::y()).magnitude() < 1e-5);
+        close(Some(ray), Vec3::new(1.0, 1.0, 0.0).normalize());
     }
 }
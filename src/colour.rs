@@ -1,5 +1,8 @@
-use crate::{material::WAVELENGTH_RANGE, prelude::*};
-use rand::Rng;
+use crate::{
+    material::{MAX_WAVELENGTH, MIN_WAVELENGTH, WAVELENGTH_RANGE},
+    prelude::*,
+};
+use std::str::FromStr;
 
 #[derive(Debug)]
 pub struct PiecewiseGaussian {
@@ -54,7 +57,7 @@ pub fn xyz_to_rgb(xyz: Vec3) -> Vec3 {
     Vec3::new(r, g, b)
 }
 
-pub fn to_u32(rgb: Vec3) -> u32 {
+pub fn to_rgb8(rgb: Vec3) -> [u8; 3] {
     // TODO TONEMAPPING
 
     // gamma correction
@@ -62,18 +65,197 @@ pub fn to_u32(rgb: Vec3) -> u32 {
     let g = rgb.y.powf(1.0 / 2.2);
     let b = rgb.z.powf(1.0 / 2.2);
 
-    // convert to u32
-    let r = ((r * 255.0) as u8) as u32;
-    let g = ((g * 255.0) as u8) as u32;
-    let b = ((b * 255.0) as u8) as u32;
+    [(r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8]
+}
+
+pub fn to_u32(rgb: Vec3) -> u32 {
+    let [r, g, b] = to_rgb8(rgb).map(|v| v as u32);
 
     r << 16 | g << 8 | b
 }
 
-pub fn sample_wl(rng: &mut impl Rng) -> f32 {
-    rng.gen_range(380.0..750.0)
+pub const HERO_WAVELENGTHS: usize = 4;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WavelengthSampling {
+    Uniform,
+    // proportional to a fit of the colour matching functions' sum, fewer samples are spent on
+    // wavelengths that barely show
+    #[default]
+    Visible,
+}
+
+impl FromStr for WavelengthSampling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "uniform" => Ok(Self::Uniform),
+            "visible" => Ok(Self::Visible),
+            _ => Err(format!(
+                "unknown wavelength sampling \"{s}\", expected one of uniform, visible"
+            )),
+        }
+    }
+}
+
+// sech^2(a (wl - c)) from pbrt-v4 truncated to the rendered range, it integrates to
+// tanh(a (wl - c)) / a
+const VISIBLE_A: f32 = 0.0072;
+const VISIBLE_CENTRE: f32 = 538.0;
+
+fn visible_tanh(wavelength: f32) -> f32 {
+    (VISIBLE_A * (wavelength - VISIBLE_CENTRE)).tanh()
+}
+
+pub fn visible_wavelengths_pdf(wavelength: f32) -> f32 {
+    if !(MIN_WAVELENGTH..=MAX_WAVELENGTH).contains(&wavelength) {
+        return 0.0;
+    }
+    let norm = visible_tanh(MAX_WAVELENGTH) - visible_tanh(MIN_WAVELENGTH);
+    VISIBLE_A / norm / (VISIBLE_A * (wavelength - VISIBLE_CENTRE)).cosh().powi(2)
+}
+
+// u in [0, 1)
+pub fn sample_visible_wavelengths(u: f32) -> f32 {
+    let (min, max) = (visible_tanh(MIN_WAVELENGTH), visible_tanh(MAX_WAVELENGTH));
+    let wavelength = VISIBLE_CENTRE + (min + u * (max - min)).atanh() / VISIBLE_A;
+    wavelength.clamp(MIN_WAVELENGTH, MAX_WAVELENGTH)
+}
+
+// a hero wavelength & evenly spaced secondaries sharing one path, each with its pdf in 1/nm
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SampledWavelengths {
+    pub lambda: [f32; HERO_WAVELENGTHS],
+    pub pdf: [f32; HERO_WAVELENGTHS],
+}
+
+impl SampledWavelengths {
+    // u in [0, 1), the wavelengths are stratified in u
+    pub fn sample(u: f32, sampling: WavelengthSampling) -> Self {
+        let u = |i: usize| (u + i as f32 / HERO_WAVELENGTHS as f32).fract();
+        match sampling {
+            WavelengthSampling::Uniform => Self::sample_uniform(u(0)),
+            WavelengthSampling::Visible => {
+                let lambda = std::array::from_fn(|i| sample_visible_wavelengths(u(i)));
+                Self {
+                    lambda,
+                    pdf: lambda.map(visible_wavelengths_pdf),
+                }
+            }
+        }
+    }
+
+    pub fn sample_uniform(u: f32) -> Self {
+        Self {
+            lambda: std::array::from_fn(|i| {
+                let u = (u + i as f32 / HERO_WAVELENGTHS as f32).fract();
+                MIN_WAVELENGTH + u * WAVELENGTH_RANGE
+            }),
+            pdf: [1.0 / WAVELENGTH_RANGE; HERO_WAVELENGTHS],
+        }
+    }
+
+    pub fn hero(&self) -> f32 {
+        self.lambda[0]
+    }
+
+    // after a wavelength dependent direction only the hero's path is valid, it then stands in
+    // for the whole estimate
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+        self.pdf[1..].fill(0.0);
+        self.pdf[0] /= HERO_WAVELENGTHS as f32;
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdf[1..].iter().all(|&pdf| pdf == 0.0)
+    }
+
+    // the average of every wavelength's estimate, wavelengths with a pdf of 0 add nothing
+    pub fn to_rgb(&self, radiance: [f32; HERO_WAVELENGTHS]) -> Vec3 {
+        let xyz = self
+            .lambda
+            .iter()
+            .zip(self.pdf)
+            .zip(radiance)
+            .filter(|&((_, pdf), radiance)| pdf != 0.0 && radiance != 0.0)
+            .map(|((&wl, pdf), radiance)| {
+                Vec3::new(x_bar(wl), y_bar(wl), z_bar(wl)) * radiance / pdf
+            })
+            .sum::<Vec3>();
+        xyz_to_rgb(xyz / HERO_WAVELENGTHS as f32)
+    }
+}
+
+pub fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
 }
 
-pub fn inverse_pdf_wl(_: f32) -> f32 {
-    WAVELENGTH_RANGE
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hero_wavelengths() {
+        let mut wavelengths = SampledWavelengths::sample_uniform(0.9);
+        assert_eq!(wavelengths.hero(), 380.0 + 0.9 * WAVELENGTH_RANGE);
+        let mut sorted = wavelengths.lambda;
+        sorted.sort_by(f32::total_cmp);
+        for pair in sorted.windows(2) {
+            assert!((pair[1] - pair[0] - 0.25 * WAVELENGTH_RANGE).abs() < 1e-3);
+        }
+
+        // terminating keeps the hero's estimate unbiased
+        let radiance = [1.0, 0.0, 0.0, 0.0];
+        let before = wavelengths.to_rgb(radiance);
+        wavelengths.terminate_secondary();
+        wavelengths.terminate_secondary();
+        assert!(wavelengths.secondary_terminated());
+        assert!((wavelengths.to_rgb(radiance) - before * 4.0).abs().max() < 1e-4);
+        assert_eq!(wavelengths.to_rgb([0.0, 5.0, 5.0, 5.0]), Vec3::zeros());
+    }
+
+    #[test]
+    fn visible_wavelengths() {
+        // a normalised pdf that inverts its cdf
+        let steps = 3700;
+        let dx = WAVELENGTH_RANGE / steps as f32;
+        let mut cdf = 0.0;
+        for i in 0..steps {
+            let wl = MIN_WAVELENGTH + (i as f32 + 0.5) * dx;
+            let u = cdf + 0.5 * visible_wavelengths_pdf(wl) * dx;
+            assert!((sample_visible_wavelengths(u) - wl).abs() < 0.05, "{wl}");
+            cdf += visible_wavelengths_pdf(wl) * dx;
+        }
+        assert!((cdf - 1.0).abs() < 1e-3, "{cdf}");
+        assert_eq!(visible_wavelengths_pdf(300.0), 0.0);
+
+        // both estimate the same luminance of a flat spectrum, the visible one with less variance
+        let estimate = |sampling| {
+            let values = (0..256)
+                .map(|i| SampledWavelengths::sample((i as f32 + 0.5) / 256.0, sampling))
+                .map(|w| w.to_rgb([1.0; HERO_WAVELENGTHS]))
+                .collect::<Vec<_>>();
+            let mean = values.iter().sum::<Vec3>() / values.len() as f32;
+            let variance = values
+                .iter()
+                .map(|v| (v - mean).norm_squared())
+                .sum::<f32>();
+            (mean, variance)
+        };
+        let (uniform, uniform_variance) = estimate(WavelengthSampling::Uniform);
+        let (visible, visible_variance) = estimate(WavelengthSampling::Visible);
+        assert!(
+            (uniform - visible).abs().max() < 1e-2,
+            "{uniform:?} {visible:?}"
+        );
+        assert!(visible_variance < uniform_variance);
+    }
 }
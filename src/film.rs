@@ -0,0 +1,222 @@
+use crate::prelude::*;
+use rayon::prelude::*;
+use std::{f32::consts::PI, str::FromStr};
+
+// reconstruction filters over pixel offsets, separable in x & y
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Filter {
+    // the average of the samples inside each pixel
+    #[default]
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+    Lanczos,
+}
+
+const GAUSSIAN_SIGMA: f32 = 0.5;
+// B = C = 1/3 as recommended by mitchell & netravali
+const MITCHELL_B: f32 = 1.0 / 3.0;
+const MITCHELL_C: f32 = 1.0 / 3.0;
+const LANCZOS_TAU: f32 = 3.0;
+
+impl Filter {
+    // half width in pixels, the weight is 0 beyond it
+    pub fn radius(self) -> f32 {
+        match self {
+            Self::Box => 0.5,
+            Self::Tent => 1.0,
+            Self::Gaussian => 3.0 * GAUSSIAN_SIGMA,
+            Self::Mitchell => 2.0,
+            Self::Lanczos => LANCZOS_TAU,
+        }
+    }
+
+    pub fn eval(self, offset: Vec2) -> f32 {
+        self.eval_1d(offset.x) * self.eval_1d(offset.y)
+    }
+
+    fn eval_1d(self, x: f32) -> f32 {
+        let x = x.abs();
+        let radius = self.radius();
+        if x > radius {
+            return 0.0;
+        }
+        match self {
+            Self::Box => 1.0,
+            Self::Tent => radius - x,
+            // shifted down so it reaches 0 at the radius
+            Self::Gaussian => {
+                let gaussian = |x: f32| (-x * x / (2.0 * GAUSSIAN_SIGMA.powi(2))).exp();
+                gaussian(x) - gaussian(radius)
+            }
+            Self::Mitchell => {
+                let (b, c) = (MITCHELL_B, MITCHELL_C);
+                if x < 1.0 {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x.powi(3)
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                } else {
+                    ((-b - 6.0 * c) * x.powi(3)
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                }
+            }
+            Self::Lanczos => sinc(x) * sinc(x / LANCZOS_TAU),
+        }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "box" => Ok(Self::Box),
+            "tent" => Ok(Self::Tent),
+            "gaussian" => Ok(Self::Gaussian),
+            "mitchell" => Ok(Self::Mitchell),
+            "lanczos" => Ok(Self::Lanczos),
+            _ => Err(format!(
+                "unknown filter \"{s}\", expected one of box, tent, gaussian, mitchell, lanczos"
+            )),
+        }
+    }
+}
+
+// one sample per pixel, offset in [0, 1)^2 from the pixel's top left corner
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FilmSample {
+    pub offset: Vec2,
+    pub col: Vec3,
+}
+
+// filter weighted sums of every sample within the filter's radius of each pixel centre, divided by
+// the sum of the weights so negative lobes & the image borders don't change the brightness
+#[derive(Debug, Clone)]
+pub struct Film {
+    width: usize,
+    height: usize,
+    filter: Filter,
+    sums: Vec<Vec3>,
+    weights: Vec<f32>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize, filter: Filter) -> Self {
+        Self {
+            width,
+            height,
+            filter,
+            sums: vec![Vec3::zeros(); width * height],
+            weights: vec![0.0; width * height],
+        }
+    }
+
+    // splats a pass of one sample per pixel, gathered per pixel so rows can be filled in parallel
+    pub fn add_samples(&mut self, samples: &[FilmSample]) {
+        assert_eq!(samples.len(), self.width * self.height);
+        let (width, height, filter) = (self.width, self.height, self.filter);
+        let reach = (filter.radius() + 0.5).ceil() as isize;
+
+        self.sums
+            .par_chunks_mut(width)
+            .zip(self.weights.par_chunks_mut(width))
+            .enumerate()
+            .for_each(|(y, (sums, weights))| {
+                for x in 0..width {
+                    let centre = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+                    for sy in y as isize - reach..=y as isize + reach {
+                        if sy < 0 || sy >= height as isize {
+                            continue;
+                        }
+                        for sx in x as isize - reach..=x as isize + reach {
+                            if sx < 0 || sx >= width as isize {
+                                continue;
+                            }
+                            let sample = &samples[sy as usize * width + sx as usize];
+                            let pos = Vec2::new(sx as f32, sy as f32) + sample.offset;
+                            let weight = filter.eval(pos - centre);
+                            if weight != 0.0 {
+                                sums[x] += weight * sample.col;
+                                weights[x] += weight;
+                            }
+                        }
+                    }
+                }
+            });
+    }
+
+    pub fn image(&self) -> Vec<Vec3> {
+        self.sums
+            .par_iter()
+            .zip(&self.weights)
+            .map(|(&sum, &weight)| {
+                if weight.abs() > 1e-8 {
+                    sum / weight
+                } else {
+                    Vec3::zeros()
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters() {
+        let filters = [
+            Filter::Box,
+            Filter::Tent,
+            Filter::Gaussian,
+            Filter::Mitchell,
+            Filter::Lanczos,
+        ];
+        for filter in filters {
+            assert!(filter.eval(Vec2::zeros()) > 0.0);
+            assert_eq!(filter.eval(Vec2::new(filter.radius() + 0.01, 0.0)), 0.0);
+            assert_eq!(
+                filter.eval(Vec2::new(0.3, -0.2)),
+                filter.eval(Vec2::new(-0.3, 0.2))
+            );
+
+            // a flat image stays flat, including at the borders
+            let mut film = Film::new(7, 5, filter);
+            for i in 0..3 {
+                let samples = (0..35)
+                    .map(|j| FilmSample {
+                        offset: Vec2::new(((j * 7 + i) % 10) as f32, ((j * 3 + i) % 10) as f32)
+                            * 0.1,
+                        col: Vec3::new(0.25, 0.5, 1.0),
+                    })
+                    .collect::<Vec<_>>();
+                film.add_samples(&samples);
+            }
+            for col in film.image() {
+                assert!(
+                    (col - Vec3::new(0.25, 0.5, 1.0)).abs().max() < 1e-5,
+                    "{filter:?}"
+                );
+            }
+        }
+
+        // negative lobes
+        assert!(Filter::Mitchell.eval_1d(1.5) < 0.0);
+        assert!(Filter::Lanczos.eval_1d(1.5) < 0.0);
+        assert!("mitchell".parse::<Filter>().is_ok() && "sinc".parse::<Filter>().is_err());
+    }
+}
@@ -1,77 +1,77 @@
-use crate::prelude::*;
-use core::ops::Range;
-use rand::{thread_rng, Rng};
+use crate::{
+    colour::{SampledWavelengths, HERO_WAVELENGTHS},
+    prelude::*,
+    sampler::Sampler,
+};
 
 pub struct NaiveSpectral {}
 
-const MAX_DEPTH: u64 = 50;
+pub const DEFAULT_MAX_DEPTH: u64 = 50;
 const RUSSIAN_ROULETTE_THRESHOLD: u64 = 6;
 
 impl NaiveSpectral {
-    pub fn radiance(ray: &mut Ray, bvh: &Bvh, wavelength: f32, rng: &mut impl Rng) -> (f32, u64) {
-        let (mut tp, mut out): (_, f32) = (1.0, 0.0);
+    // the path follows the hero wavelength, the secondaries share it until a dispersive material
+    pub fn radiance(
+        ray: &mut Ray,
+        scene: &Scene,
+        wavelengths: &mut SampledWavelengths,
+        max_depth: u64,
+        sampler: &mut dyn Sampler,
+    ) -> ([f32; HERO_WAVELENGTHS], u64) {
+        let (mut tp, mut out) = ([1.0; HERO_WAVELENGTHS], [0.0f32; HERO_WAVELENGTHS]);
 
         let mut depth = 0;
 
-        while depth < MAX_DEPTH {
+        while depth < max_depth {
             depth += 1;
 
-            let ranges = bvh.traverse(ray);
-            let ints = get_hits(ranges);
-
-            if let Some(int) = sort_intersections(ray, ints).get(0) {
-                let mat = unsafe { &MATERIALS[int.mat] };
+            if let Some(int) = &scene.intersect(ray) {
+                let mat = &scene.materials[int.mat];
 
                 let wo = ray.dir;
 
-                let le = mat.spectral_radiance(int, wo, wavelength);
-
-                out += le * tp;
+                for i in 0..HERO_WAVELENGTHS {
+                    let le = mat.spectral_radiance(int, wo, wavelengths.lambda[i]);
+                    out[i] += le * tp[i];
+                }
 
-                let exit = mat.scatter(int, ray, wavelength, rng);
+                let exit = mat.scatter(int, ray, wavelengths.hero(), sampler);
 
                 if exit {
                     break;
                 }
 
-                if !mat.delta_dist() {
-                    tp *= mat.eval_li_spdf(int, wo, ray.dir, wavelength);
-                } else {
-                    tp *= mat.eval_li(int, wo, ray.dir, wavelength);
+                if mat.dispersive() {
+                    wavelengths.terminate_secondary();
+                }
+
+                for (tp, &wl) in tp.iter_mut().zip(&wavelengths.lambda) {
+                    if !mat.delta_dist() {
+                        *tp *= mat.eval_li_spdf(int, wo, ray.dir, wl);
+                    } else {
+                        *tp *= mat.eval_li(int, wo, ray.dir, wl);
+                    }
                 }
 
                 if depth > RUSSIAN_ROULETTE_THRESHOLD {
-                    let p = tp;
-                    let mut rng = thread_rng();
-                    if rng.gen::<f32>() > p {
+                    let p = tp
+                        .iter()
+                        .zip(wavelengths.pdf)
+                        .filter(|&(_, pdf)| pdf != 0.0)
+                        .fold(0.0f32, |p, (&tp, _)| p.max(tp));
+                    // samplers can return exactly 0, which would survive & divide by zero
+                    if p <= 0.0 || sampler.get_1d() > p {
                         break;
                     }
-                    tp /= p;
+                    tp = tp.map(|tp| tp / p);
                 }
             } else {
-                return (0.0, depth);
+                return ([0.0; HERO_WAVELENGTHS], depth);
             }
         }
-        if out.is_nan() {
-            return (0.0, 0);
+        if out.iter().any(|v| v.is_nan()) {
+            return ([0.0; HERO_WAVELENGTHS], 0);
         }
         (out, depth)
     }
 }
-
-fn get_hits(ranges: Vec<Range<usize>>) -> Vec<&'static Triangle> {
-    ranges
-        .into_iter()
-        .flat_map(|r| unsafe { &TRIANGLES[r] })
-        .collect()
-}
-
-fn sort_intersections(ray: &Ray, tris: Vec<&Triangle>) -> Vec<Intersection> {
-    let mut tris: Vec<_> = tris
-        .into_iter()
-        .filter_map(|v| v.intersect(ray))
-        .filter(|v| v.t > 0.0)
-        .collect();
-    tris.sort_by(|a, b| utility::float_cmp(a.t, b.t));
-    tris
-}
@@ -0,0 +1,293 @@
+use crate::{
+    aperture::concentric_disk,
+    camera::{basis, Camera, CameraSample},
+    load_error::LoadError,
+    material::{refract, SpectralRefract},
+    prelude::*,
+};
+use std::path::Path;
+
+// matches the f-stop conversion of perspective cameras
+const SENSOR_WIDTH: f32 = 0.036;
+// fraunhofer d, F & C lines in micrometres, used to fit a dispersion curve to an abbe number
+const D_LINE: f32 = 0.5876;
+const F_LINE: f32 = 0.4861;
+const C_LINE: f32 = 0.6563;
+
+// one interface of a lens table in metres
+#[derive(Debug)]
+struct Surface {
+    // positive when the centre of curvature is toward the film, 0 for the aperture stop
+    radius: f32,
+    // vertex position on the optical axis, the rear surface is at 0 & the scene toward +z
+    z: f32,
+    aperture_radius: f32,
+    // between this surface & the next one toward the film, None for air
+    medium: Option<SpectralRefract>,
+}
+
+// surfaces from the front of the lens to the film
+#[derive(Debug)]
+pub struct LensTable {
+    surfaces: Vec<Surface>,
+}
+
+impl LensTable {
+    // rows of radius, thickness, ior & aperture diameter in mm with an optional abbe number,
+    // an ior of 0 or 1 is air & a radius of 0 marks the aperture stop, # starts a comment
+    pub fn parse(src: &str) -> Result<Self, LoadError> {
+        let mut rows = Vec::new();
+        for (i, line) in src.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let row = line
+                .split_whitespace()
+                .map(str::parse::<f32>)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| LoadError::Parse(format!("line {}: {e}", i + 1)))?;
+            if row.len() != 4 && row.len() != 5 {
+                return Err(LoadError::Parse(format!(
+                    "line {}: expected 4 or 5 columns, got {}",
+                    i + 1,
+                    row.len()
+                )));
+            }
+            rows.push(row);
+        }
+        if rows.is_empty() {
+            return Err(LoadError::MissingAttribute("lens surfaces"));
+        }
+
+        // the last thickness is the distance to the film, replaced when focusing
+        let mut z = 0.0;
+        let mut surfaces = Vec::with_capacity(rows.len());
+        for row in rows[..rows.len() - 1].iter().rev() {
+            z += row[1] * 1e-3;
+            surfaces.push(z);
+        }
+        surfaces.reverse();
+        surfaces.push(0.0);
+
+        let surfaces = rows
+            .iter()
+            .zip(surfaces)
+            .map(|(row, z)| Surface {
+                radius: row[0] * 1e-3,
+                z,
+                aperture_radius: 0.5 * row[3] * 1e-3,
+                medium: (row[2] > 1.0).then(|| dispersive_glass(row[2], row.get(4).copied())),
+            })
+            .collect();
+        Ok(Self { surfaces })
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    // through every surface toward the scene or the film in lens space, None if the ray is blocked
+    fn trace(
+        &self,
+        mut origin: Vec3,
+        mut dir: Vec3,
+        wavelength: f32,
+        to_scene: bool,
+    ) -> Option<(Vec3, Vec3)> {
+        let eta = |medium: Option<&SpectralRefract>| medium.map_or(1.0, |m| m.ior(wavelength));
+
+        let n = self.surfaces.len();
+        for step in 0..n {
+            let i = if to_scene { n - 1 - step } else { step };
+            let surface = &self.surfaces[i];
+
+            let t = if surface.radius == 0.0 {
+                (surface.z - origin.z) / dir.z
+            } else {
+                let centre = Vec3::new(0.0, 0.0, surface.z - surface.radius);
+                let oc = origin - centre;
+                let b = oc.dot(&dir);
+                let discriminant = b * b - oc.magnitude_squared() + surface.radius.powi(2);
+                if discriminant < 0.0 {
+                    return None;
+                }
+                // the vertex is on the near side of the sphere when the centre is ahead of the ray
+                if (dir.z > 0.0) != (surface.radius > 0.0) {
+                    -b - discriminant.sqrt()
+                } else {
+                    -b + discriminant.sqrt()
+                }
+            };
+            if t.is_nan() || t <= 0.0 {
+                return None;
+            }
+            origin += t * dir;
+            if origin.xy().magnitude_squared() > surface.aperture_radius.powi(2) {
+                return None;
+            }
+
+            if surface.radius != 0.0 {
+                let centre = Vec3::new(0.0, 0.0, surface.z - surface.radius);
+                let mut nor = (origin - centre).normalize();
+                if nor.dot(&dir) > 0.0 {
+                    nor = -nor;
+                }
+                let front = eta(i
+                    .checked_sub(1)
+                    .and_then(|i| self.surfaces[i].medium.as_ref()));
+                let back = eta(surface.medium.as_ref());
+                let eta_fraction = if to_scene { back / front } else { front / back };
+                dir = refract(dir, nor, eta_fraction)?.normalize();
+            }
+        }
+        Some((origin, dir))
+    }
+
+    // distance behind the rear surface where an on axis point object_z in front of it is imaged
+    fn image_distance(&self, object_z: f32, wavelength: f32) -> Option<f32> {
+        let front = &self.surfaces[0];
+        let height = 0.05 * front.aperture_radius;
+        let (origin, dir) = if object_z.is_finite() {
+            let origin = Vec3::new(0.0, 0.0, object_z);
+            (
+                origin,
+                (Vec3::new(height, 0.0, front.z) - origin).normalize(),
+            )
+        } else {
+            (Vec3::new(height, 0.0, front.z + 1.0), -Vec3::z())
+        };
+
+        let (origin, dir) = self.trace(origin, dir, wavelength, false)?;
+        let t = -origin.x / dir.x;
+        (t > 0.0).then(|| -(origin.z + t * dir.z))
+    }
+
+    // film distance that brings points focus_dist in front of the film into focus
+    fn focus(&self, focus_dist: f32) -> Option<f32> {
+        let wavelength = 1e3 * D_LINE;
+        let mut film_distance = self.image_distance(f32::INFINITY, wavelength)?;
+        for _ in 0..8 {
+            film_distance = self.image_distance(focus_dist - film_distance, wavelength)?;
+        }
+        Some(film_distance)
+    }
+}
+
+// cauchy's equation through the d line index with the abbe number's F - C dispersion
+fn dispersive_glass(ior: f32, abbe: Option<f32>) -> SpectralRefract {
+    let b = abbe.map_or(0.0, |abbe| {
+        (ior - 1.0) / abbe / (F_LINE.powi(-2) - C_LINE.powi(-2))
+    });
+    let a = ior - b / (D_LINE * D_LINE);
+    SpectralRefract::new(Spectrum::Cauchy { a, b })
+}
+
+// traces every wavelength through the lens elements, rays blocked by an element's rim or the
+// aperture stop are lost which gives natural vignetting, the origin is the centre of the film
+pub struct LensSystem {
+    origin: Vec3,
+    forward: Vec3,
+    right: Vec3,
+    up: Vec3,
+    table: LensTable,
+    film_distance: f32,
+    film_size: Vec2,
+}
+
+impl LensSystem {
+    pub fn new(
+        origin: Vec3,
+        look_at: Vec3,
+        up: Vec3,
+        table: LensTable,
+        focus_dist: f32,
+        aspect_ratio: f32,
+    ) -> Result<Self, LoadError> {
+        let (forward, right, up) = basis(origin, look_at, up);
+        let film_distance = table.focus(focus_dist).ok_or_else(|| {
+            LoadError::Parse(format!("lens can't focus at a distance of {focus_dist}"))
+        })?;
+
+        Ok(Self {
+            origin,
+            forward,
+            right,
+            up,
+            table,
+            film_distance,
+            film_size: Vec2::new(SENSOR_WIDTH, SENSOR_WIDTH / aspect_ratio),
+        })
+    }
+}
+
+impl Camera for LensSystem {
+    fn get_ray(&self, sample: &CameraSample) -> Option<Ray> {
+        let (u, v) = (sample.film.x, sample.film.y);
+        // the lens flips the image so the film is mirrored
+        let film = Vec3::new(
+            (0.5 - u) * self.film_size.x,
+            (v - 0.5) * self.film_size.y,
+            -self.film_distance,
+        );
+        let rear = self.table.surfaces.last().unwrap();
+        let pupil = concentric_disk(sample.lens) * rear.aperture_radius;
+        let dir = (Vec3::new(pupil.x, pupil.y, 0.0) - film).normalize();
+
+        let (origin, dir) = self.table.trace(film, dir, sample.wavelength, true)?;
+        let to_world = |v: Vec3| v.x * self.right + v.y * self.up + v.z * self.forward;
+        Some(Ray::new_with_time(
+            self.origin + to_world(origin),
+            to_world(dir),
+            sample.time,
+        ))
+    }
+
+    fn dispersive(&self) -> bool {
+        self.table
+            .surfaces
+            .iter()
+            .any(|s| s.medium.as_ref().is_some_and(SpectralRefract::dispersive))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn double_gauss() {
+        let table = LensTable::open("scenes/lenses/dgauss50mm.dat").unwrap();
+        // a 50mm lens focused at infinity
+        let film_distance = table.focus(f32::INFINITY).unwrap();
+        assert!((0.02..0.06).contains(&film_distance), "{film_distance}");
+
+        // rays from a film point all meet again at the focus distance
+        let cam = LensSystem::new(Vec3::zeros(), Vec3::z(), Vec3::y(), table, 2.0, 1.0).unwrap();
+        let hits = [
+            Vec2::new(0.5, 0.5),
+            Vec2::new(0.6, 0.5),
+            Vec2::new(0.5, 0.35),
+        ]
+        .map(|lens| {
+            let ray = cam
+                .get_ray(&CameraSample::new(Vec2::new(0.5, 0.5), lens, 0.0, 587.6))
+                .unwrap();
+            let t = (2.0 - cam.film_distance - ray.origin.z) / ray.dir.z;
+            (ray.origin + t * ray.dir).xy()
+        });
+        for hit in hits {
+            assert!(hit.magnitude() < 1e-3, "{hit:?}");
+        }
+
+        // the focus shifts with wavelength
+        let (blue, red) = (
+            cam.table.image_distance(f32::INFINITY, 400.0).unwrap(),
+            cam.table.image_distance(f32::INFINITY, 700.0).unwrap(),
+        );
+        assert!((blue - red).abs() > 1e-6, "{blue} {red}");
+
+        assert!(LensTable::parse("# nothing").is_err());
+        assert!(LensTable::parse("1 2 3").is_err());
+    }
+}
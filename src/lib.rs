@@ -0,0 +1,71 @@
+pub mod animation;
+pub mod aperture;
+pub mod camera;
+pub mod colour;
+pub mod cornell_box;
+pub mod film;
+pub mod integrator;
+pub mod lens_system;
+pub mod load_error;
+pub mod load_gltf;
+pub mod load_obj;
+pub mod load_ply;
+pub mod material;
+pub mod mesh;
+pub mod primitive;
+pub mod render;
+pub mod rgb_spectrum;
+pub mod sampler;
+pub mod scene;
+pub mod scene_file;
+pub mod shapes;
+pub mod spectrum;
+#[cfg(test)]
+mod temp_dir;
+pub mod texture;
+pub mod transform;
+pub mod triangle;
+
+use derive_new::new;
+use rgb_spectrum::SigmoidPolynomial;
+
+pub type Vec3 = nalgebra::Vector3<f32>;
+pub type Ray = utility::Ray;
+pub type Vec2 = nalgebra::Vector2<f32>;
+pub type Bvh = bvh::Bvh;
+
+pub mod prelude {
+    pub use super::{Bvh, Intersection, Ray, Vec2, Vec3};
+    pub use crate::{
+        camera::Camera,
+        integrator::NaiveSpectral,
+        material::*,
+        mesh::Mesh,
+        primitive::{Geometry, Primitive},
+        scene::{Instance, Scene},
+        shapes::{Cylinder, Disk, Quad, Sphere},
+        spectrum::Spectrum,
+        texture::Texture,
+        transform::Transform,
+        triangle::Triangle,
+    };
+    pub use utility;
+}
+
+#[allow(clippy::too_many_arguments)]
+#[derive(Debug, new)]
+pub struct Intersection {
+    pub t: f32,
+    pub pos: Vec3,
+    pub err: Vec3,
+    pub nor: Vec3,
+    pub uv: Vec2,
+    // surface partial derivatives, not normalised
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    pub out: bool,
+    pub mat: usize,
+    // upsampled vertex colours & their barycentric weights
+    #[new(default)]
+    pub colour: Option<[(SigmoidPolynomial, f32); 3]>,
+}
@@ -0,0 +1,63 @@
+use std::{fmt, io};
+
+// shared by the mesh & scene loaders, the caller knows which file failed
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Parse(String),
+    MissingAttribute(&'static str),
+    IndexOutOfRange {
+        attribute: &'static str,
+        index: usize,
+        len: usize,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "{e}"),
+            LoadError::Parse(e) => write!(f, "parse error: {e}"),
+            LoadError::MissingAttribute(attribute) => write!(f, "missing {attribute}"),
+            LoadError::IndexOutOfRange {
+                attribute,
+                index,
+                len,
+            } => write!(
+                f,
+                "{attribute} index {index} is out of range (expected less than {len})"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+// checks every index can be used with a slice of len elements
+pub fn check_indices(
+    attribute: &'static str,
+    indices: impl IntoIterator<Item = usize>,
+    len: usize,
+) -> Result<(), LoadError> {
+    match indices.into_iter().find(|i| *i >= len) {
+        Some(index) => Err(LoadError::IndexOutOfRange {
+            attribute,
+            index,
+            len,
+        }),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,376 @@
+use crate::{
+    camera::Projection,
+    colour::srgb_to_linear,
+    load_error::{check_indices, LoadError},
+    mesh::{generate_normals, remove_degenerate, weld_vertices, ImportOptions},
+    prelude::*,
+    scene_file::CameraDesc,
+    transform::Mat4,
+};
+use gltf::{camera, image::Format, mesh::Mode};
+use std::{collections::HashMap, sync::Arc};
+
+// each gltf mesh becomes one geometry, placed by an instance for every node using it
+// on failure the scene may be left with some of the file's materials & geometry
+pub fn load_gltf(
+    path: &str,
+    scene: &mut Scene,
+    options: &ImportOptions,
+) -> Result<Vec<CameraDesc>, LoadError> {
+    let (document, buffers, images) = gltf::import(path).map_err(|e| match e {
+        gltf::Error::Io(e) => LoadError::Io(e),
+        e => LoadError::Parse(e.to_string()),
+    })?;
+
+    let mut textures = HashMap::new();
+    let materials = document
+        .materials()
+        .map(|m| scene.add_material(gltf_material(&m, &images, &mut textures)))
+        .collect::<Vec<_>>();
+    let mut default_mat = None;
+
+    let mut meshes = HashMap::new();
+    let (mut cameras, mut instances) = (Vec::new(), 0);
+
+    let roots = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .map(|s| s.nodes().collect::<Vec<_>>())
+        .unwrap_or_default();
+    let mut stack = roots
+        .into_iter()
+        .map(|n| (n, Mat4::identity()))
+        .collect::<Vec<_>>();
+
+    while let Some((node, parent)) = stack.pop() {
+        let m = parent * Mat4::from(node.transform().matrix());
+        stack.extend(node.children().map(|c| (c, m)));
+
+        if let Some(camera) = node.camera() {
+            cameras.push(gltf_camera(&m, &camera.projection()));
+        }
+
+        let Some(mesh) = node.mesh() else {
+            continue;
+        };
+        let Some(transform) = Transform::new(m) else {
+            log::warn!("skipping node with a non-invertible transform in {path}");
+            continue;
+        };
+
+        let geometry = match meshes.get(&mesh.index()) {
+            Some(&idx) => idx,
+            None => {
+                let mut mat = |index: Option<usize>| match index {
+                    Some(i) => materials[i],
+                    None => *default_mat.get_or_insert_with(|| {
+                        scene.add_material(Mat::Lambertian(Lambertian::new(0.8)))
+                    }),
+                };
+                let Some(mesh_data) = gltf_mesh(&mesh, &buffers, &mut mat, options)? else {
+                    continue;
+                };
+                let idx = scene.add_geometry(mesh_data);
+                meshes.insert(mesh.index(), idx);
+                idx
+            }
+        };
+
+        scene.add_instance(Instance::new(geometry, transform, None));
+        instances += 1;
+    }
+
+    log::info!("loaded {} meshes as {instances} instances", meshes.len());
+
+    Ok(cameras)
+}
+
+// primitives are merged into one mesh keeping their own materials
+fn gltf_mesh(
+    mesh: &gltf::Mesh,
+    buffers: &[gltf::buffer::Data],
+    mat: &mut impl FnMut(Option<usize>) -> usize,
+    options: &ImportOptions,
+) -> Result<Option<Mesh>, LoadError> {
+    let (mut vertices, mut normals, mut uvs, mut triangles) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    // triangles of primitives without normals
+    let mut missing_normals = Vec::new();
+
+    for primitive in mesh.primitives() {
+        if primitive.mode() != Mode::Triangles {
+            log::warn!("skipping non-triangle primitive {:?}", primitive.mode());
+            continue;
+        }
+        let reader = primitive.reader(|b| Some(&buffers[b.index()]));
+
+        let vo = vertices.len();
+        let no = normals.len();
+        let uo = uvs.len();
+        let material = primitive.material();
+        let mat = mat(material.index());
+
+        let positions = reader
+            .read_positions()
+            .ok_or(LoadError::MissingAttribute("positions"))?;
+        vertices.extend(positions.map(Vec3::from));
+        let indices = match reader.read_indices() {
+            Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+            None => (0..vertices.len() - vo).collect::<Vec<_>>(),
+        };
+        check_indices("vertex", indices.iter().copied(), vertices.len() - vo)?;
+
+        let smooth = match reader.read_normals() {
+            Some(n) => {
+                normals.extend(n.map(|n| Vec3::from(n).normalize()));
+                true
+            }
+            None => false,
+        };
+
+        // the texture coordinate set used by the base colour texture
+        let set = material
+            .pbr_metallic_roughness()
+            .base_color_texture()
+            .map_or(0, |t| t.tex_coord());
+        let textured = match reader.read_tex_coords(set) {
+            Some(t) => {
+                // gltf's v axis points down
+                uvs.extend(t.into_f32().map(|[u, v]| Vec2::new(u, 1.0 - v)));
+                true
+            }
+            None => false,
+        };
+
+        // attributes share the position indices
+        let count = vertices.len() - vo;
+        if (smooth && normals.len() - no != count) || (textured && uvs.len() - uo != count) {
+            return Err(LoadError::Parse(
+                "primitive attributes have different counts".into(),
+            ));
+        }
+
+        if !smooth {
+            missing_normals.push(triangles.len()..triangles.len() + indices.len() / 3);
+        }
+        for tri in indices.chunks_exact(3) {
+            let pos = [tri[0] + vo, tri[1] + vo, tri[2] + vo];
+            // replaced when normals are generated
+            let nor = if smooth {
+                [tri[0] + no, tri[1] + no, tri[2] + no]
+            } else {
+                pos
+            };
+
+            let triangle = Triangle::new(pos, nor, mat);
+            triangles.push(if textured {
+                triangle.with_uv([tri[0] + uo, tri[1] + uo, tri[2] + uo])
+            } else {
+                triangle
+            });
+        }
+    }
+
+    if options.weld {
+        let welded = weld_vertices(&mut vertices, &mut Vec::new(), &mut triangles);
+        log::debug!("welded {welded} vertices");
+    }
+    for range in missing_normals {
+        generate_normals(
+            &vertices,
+            &mut triangles[range],
+            &mut normals,
+            options.normals,
+        );
+    }
+    remove_degenerate(&vertices, &mut triangles);
+    if triangles.is_empty() {
+        log::warn!("skipping mesh {:?} without triangles", mesh.name());
+        return Ok(None);
+    }
+
+    Ok(Some(Mesh::new_with_uvs(vertices, normals, uvs, triangles)))
+}
+
+// gltf cameras look down -z with y up
+fn gltf_camera(m: &Mat4, projection: &camera::Projection) -> CameraDesc {
+    let origin = m.transform_point(&Vec3::zeros().into()).coords;
+    let forward = m.transform_vector(&-Vec3::z());
+    let up = m.transform_vector(&Vec3::y());
+
+    let (hfov, projection) = match projection {
+        camera::Projection::Perspective(p) => {
+            // the image aspect ratio is only known at render time
+            let aspect = p.aspect_ratio().unwrap_or(1.0);
+            let hfov = 2.0 * ((0.5 * p.yfov()).tan() * aspect).atan();
+            (hfov, Projection::Perspective)
+        }
+        // gives a view 2 * xmag wide at the unit focus distance
+        camera::Projection::Orthographic(o) => (2.0 * o.xmag().atan(), Projection::Orthographic),
+    };
+
+    let mut desc = CameraDesc::new(
+        origin.into(),
+        (origin + forward).into(),
+        up.into(),
+        hfov.to_degrees(),
+    );
+    desc.projection = projection;
+    desc
+}
+
+fn gltf_material(
+    m: &gltf::Material,
+    images: &[gltf::image::Data],
+    textures: &mut HashMap<usize, Arc<Texture>>,
+) -> Mat {
+    let pbr = m.pbr_metallic_roughness();
+    let [r, g, b, _] = pbr.base_color_factor();
+    let base = Vec3::new(r, g, b);
+
+    let emissive = Vec3::from(m.emissive_factor()) * m.emissive_strength().unwrap_or(1.0);
+    let ior = m
+        .transmission()
+        .is_some_and(|t| t.transmission_factor() > 0.0)
+        .then(|| m.ior().unwrap_or(1.5));
+    // there is no glossy model, so metals are perfect mirrors
+    let mirror = (pbr.metallic_factor() >= 0.5).then_some(base);
+
+    imported_material(emissive, ior, mirror, || {
+        let texture = pbr.base_color_texture().map(|info| {
+            let image = info.texture().source().index();
+            textures
+                .entry(image)
+                .or_insert_with(|| Arc::new(gltf_texture(&images[image])))
+                .clone()
+        });
+        (base, texture)
+    })
+}
+
+// base colour textures are sRGB encoded unless stored as floats
+fn gltf_texture(image: &gltf::image::Data) -> Texture {
+    let (channels, bytes) = match image.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+
+    let texels = image
+        .pixels
+        .chunks_exact(channels * bytes)
+        .map(|texel| {
+            let channel = |c: usize| {
+                // greyscale & two channel images repeat their first channel
+                let c = if channels < 3 { 0 } else { c };
+                let v = &texel[c * bytes..(c + 1) * bytes];
+                match bytes {
+                    1 => srgb_to_linear(v[0] as f32 / 255.0),
+                    2 => srgb_to_linear(u16::from_le_bytes([v[0], v[1]]) as f32 / 65535.0),
+                    _ => f32::from_le_bytes([v[0], v[1], v[2], v[3]]),
+                }
+            };
+            Vec3::new(channel(0), channel(1), channel(2))
+        })
+        .collect();
+
+    Texture::new(image.width as usize, image.height as usize, texels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mesh::NormalGeneration, temp_dir::TempDir};
+
+    // a triangle placed twice, a red diffuse material & a camera looking down -z
+    const GLTF: &str = r#"{
+  "asset": { "version": "2.0" },
+  "scene": 0,
+  "scenes": [{ "nodes": [0, 1, 2] }],
+  "nodes": [
+    { "mesh": 0 },
+    { "mesh": 0, "translation": [2.0, 0.0, 0.0] },
+    { "camera": 0, "translation": [0.0, 0.0, 5.0] }
+  ],
+  "cameras": [{ "type": "perspective", "perspective": { "yfov": 0.8, "znear": 0.1 } }],
+  "materials": [{ "pbrMetallicRoughness": { "baseColorFactor": [0.8, 0.1, 0.1, 1.0], "metallicFactor": 0.0 } }],
+  "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 }, "material": 0 }] }],
+  "accessors": [{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0.0, 0.0, 0.0], "max": [1.0, 1.0, 0.0] }],
+  "bufferViews": [{ "buffer": 0, "byteLength": 36 }],
+  "buffers": [{ "byteLength": 36, "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA" }]
+}"#;
+
+    #[test]
+    fn load_gltf_scene() {
+        let dir = TempDir::new("load_gltf_scene");
+        let path = dir.write("triangle.gltf", GLTF);
+
+        let mut scene = Scene::new();
+        let cameras = load_gltf(
+            path.to_str().unwrap(),
+            &mut scene,
+            &ImportOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(scene.geometry.len(), 1);
+        assert_eq!(scene.instances.len(), 2);
+        assert_eq!(scene.materials.len(), 1);
+        let Geometry::Mesh(mesh) = &scene.geometry[0] else {
+            panic!("expected a mesh");
+        };
+        // smooth normals generated per vertex
+        assert_eq!(mesh.normals, [Vec3::z(); 3]);
+
+        assert_eq!(cameras.len(), 1);
+        assert_eq!(cameras[0].origin, [0.0, 0.0, 5.0]);
+        assert_eq!(cameras[0].look_at, [0.0, 0.0, 4.0]);
+        assert_eq!(cameras[0].up, [0.0, 1.0, 0.0]);
+
+        scene.build_bvh();
+        let ray = Ray::new(Vec3::new(2.2, 0.2, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let int = scene.intersect(&ray).unwrap();
+        assert!((int.t - 1.0).abs() < 1e-5);
+        assert_eq!(int.mat, 0);
+    }
+
+    #[test]
+    fn gltf_import_options() {
+        // two unindexed triangles sharing an edge
+        let quad = GLTF
+            .replace("\"count\": 3", "\"count\": 6")
+            .replace("36", "72")
+            .replace(
+                "AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA",
+                "AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAACAPwAAgD8AAAAAAAAAAAAAAAAAAAAAAACAPwAAgD8AAAAAAAAAAAAAgD8AAAAA",
+            );
+        let dir = TempDir::new("gltf_import_options");
+        let path = dir.write("quad.gltf", quad);
+
+        let load = |options: ImportOptions| {
+            let mut scene = Scene::new();
+            load_gltf(path.to_str().unwrap(), &mut scene, &options).unwrap();
+            let Geometry::Mesh(mesh) = scene.geometry.remove(0) else {
+                panic!("expected a mesh");
+            };
+            mesh
+        };
+        let welded = load(ImportOptions::default());
+        let flat = load(ImportOptions {
+            normals: NormalGeneration::Flat,
+            weld: false,
+        });
+
+        assert_eq!(welded.vertices.len(), 4);
+        assert_eq!(welded.triangles.len(), 2);
+        assert_eq!(flat.vertices.len(), 6);
+        assert_eq!(flat.normals, [Vec3::z(); 2]);
+    }
+}
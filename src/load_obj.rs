@@ -1,74 +1,355 @@
-use crate::prelude::*;
+use crate::{
+    load_error::{check_indices, LoadError},
+    mesh::{generate_normals, remove_degenerate, weld_vertices, ImportOptions},
+    prelude::*,
+};
+use std::{collections::HashMap, fs::File, io::BufReader, path::Path, sync::Arc};
 
-#[allow(dead_code)]
-pub unsafe fn load_obj(path: &str, scale: f32, offset: Vec3, mat_index: usize) {
-    let (models, _) = tobj::load_obj(
-        path,
+// all models in the file are merged into a single object space mesh, if materials are
+// given the mtl materials are appended to them and mat_index is only used for faces without one
+pub fn load_obj(
+    path: &str,
+    mat_index: usize,
+    materials: Option<&mut Vec<Mat>>,
+    options: &ImportOptions,
+) -> Result<Mesh, LoadError> {
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    let mut reader = BufReader::new(File::open(path)?);
+    let (models, mtl) = tobj::load_obj_buf(
+        &mut reader,
         &tobj::LoadOptions {
             triangulate: true,
             ignore_points: true,
             ignore_lines: true,
             ..Default::default()
         },
+        |mtl| tobj::load_mtl(dir.join(mtl)),
     )
-    .unwrap();
+    .map_err(|e| LoadError::Parse(e.to_string()))?;
 
-    let mut total = 0;
+    // only appended once the mesh has loaded
+    let mut mtl_materials = Vec::new();
+    let mtl_indices = match (&materials, mtl) {
+        (Some(materials), Ok(mtl)) => {
+            let mut textures = HashMap::new();
+            mtl_materials = mtl
+                .iter()
+                .map(|m| mtl_material(m, dir, &mut textures))
+                .collect();
+            (materials.len()..materials.len() + mtl_materials.len()).collect()
+        }
+        (Some(_), Err(e)) => {
+            log::warn!("failed to load materials for {path}: {e}");
+            Vec::new()
+        }
+        (None, _) => Vec::new(),
+    };
+
+    let (mut vertices, mut normals, mut uvs, mut triangles) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    // triangles of models without normals
+    let mut missing_normals = Vec::new();
 
     for m in models.iter() {
         let mesh = &m.mesh;
 
-        let vo = VERTICES.len();
-        let no = NORMALS.len();
+        let vo = vertices.len();
+        let no = normals.len();
+        let uo = uvs.len();
+        let mat = mesh
+            .material_id
+            .and_then(|id| mtl_indices.get(id).copied())
+            .unwrap_or(mat_index);
 
         // load vertices
         for j in 0..mesh.positions.len() / 3 {
             let i = j * 3;
-            VERTICES.push(
-                Vec3::new(
-                    mesh.positions[i] * scale,
-                    mesh.positions[i + 1] * scale,
-                    mesh.positions[i + 2] * scale,
-                ) + offset,
-            )
+            vertices.push(Vec3::new(
+                mesh.positions[i],
+                mesh.positions[i + 1],
+                mesh.positions[i + 2],
+            ))
         }
 
         // load normals
         for j in 0..mesh.normals.len() / 3 {
             let i = j * 3;
-            NORMALS.push(Vec3::new(
+            normals.push(Vec3::new(
                 mesh.normals[i],
                 mesh.normals[i + 1],
                 mesh.normals[i + 2],
             ))
         }
 
+        // load texture coordinates
+        for j in 0..mesh.texcoords.len() / 2 {
+            let i = j * 2;
+            uvs.push(Vec2::new(mesh.texcoords[i], mesh.texcoords[i + 1]))
+        }
+
         // create triangles
         let ilen = mesh.indices.len();
-        let nilen = mesh.normal_indices.len();
-        assert_eq!(ilen % 3, 0);
-        assert_eq!(ilen, nilen);
+        let has_normals = mesh.normal_indices.len() == ilen;
+        let has_uvs = mesh.texcoord_indices.len() == ilen;
+        if !has_normals {
+            missing_normals.push(triangles.len()..triangles.len() + ilen / 3);
+        }
+        check_mesh_indices(mesh)?;
 
         for j in 0..ilen / 3 {
             let i = j * 3;
 
-            TRIANGLES.push(Triangle::new(
-                [
-                    mesh.indices[i] as usize + vo,
-                    mesh.indices[i + 1] as usize + vo,
-                    mesh.indices[i + 2] as usize + vo,
-                ],
+            let pos = [
+                mesh.indices[i] as usize + vo,
+                mesh.indices[i + 1] as usize + vo,
+                mesh.indices[i + 2] as usize + vo,
+            ];
+            // replaced when normals are generated
+            let nor = if has_normals {
                 [
                     mesh.normal_indices[i] as usize + no,
                     mesh.normal_indices[i + 1] as usize + no,
                     mesh.normal_indices[i + 2] as usize + no,
-                ],
-                mat_index,
-            ));
+                ]
+            } else {
+                pos
+            };
+            let triangle = Triangle::new(pos, nor, mat);
+
+            triangles.push(if !has_uvs {
+                triangle
+            } else {
+                triangle.with_uv([
+                    mesh.texcoord_indices[i] as usize + uo,
+                    mesh.texcoord_indices[i + 1] as usize + uo,
+                    mesh.texcoord_indices[i + 2] as usize + uo,
+                ])
+            });
         }
+    }
 
-        total += mesh.indices.len() / 3;
+    if options.weld {
+        let welded = weld_vertices(&mut vertices, &mut Vec::new(), &mut triangles);
+        log::debug!("welded {welded} vertices");
+    }
+    for range in missing_normals {
+        generate_normals(
+            &vertices,
+            &mut triangles[range],
+            &mut normals,
+            options.normals,
+        );
     }
+    let removed = remove_degenerate(&vertices, &mut triangles);
+    if removed != 0 {
+        log::info!("removed {removed} degenerate triangles");
+    }
+
+    if triangles.is_empty() {
+        return Err(LoadError::MissingAttribute("faces"));
+    }
+
+    log::info!("loaded {} triangles", triangles.len());
+
+    if let Some(materials) = materials {
+        materials.extend(mtl_materials);
+    }
+    Ok(Mesh::new_with_uvs(vertices, normals, uvs, triangles))
+}
+
+// tobj already rejects out of range indices when parsing, this keeps the indexing safe regardless
+fn check_mesh_indices(mesh: &tobj::Mesh) -> Result<(), LoadError> {
+    let index = |indices: &[u32]| indices.iter().map(|i| *i as usize).collect::<Vec<_>>();
+    check_indices("vertex", index(&mesh.indices), mesh.positions.len() / 3)?;
+    check_indices(
+        "normal",
+        index(&mesh.normal_indices),
+        mesh.normals.len() / 3,
+    )?;
+    check_indices(
+        "uv",
+        index(&mesh.texcoord_indices),
+        mesh.texcoords.len() / 2,
+    )
+}
 
-    log::info!("loaded {total} triangles");
+fn mtl_material(
+    m: &tobj::Material,
+    dir: &Path,
+    textures: &mut HashMap<String, Option<Arc<Texture>>>,
+) -> Mat {
+    let rgb = |v: Option<[f32; 3]>| v.map(Vec3::from).unwrap_or_else(Vec3::zeros);
+    let (kd, ks) = (m.diffuse.map(Vec3::from), rgb(m.specular));
+    let ke = m
+        .unknown_param
+        .get("Ke")
+        .and_then(|v| {
+            let v = v
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<Vec<f32>, _>>()
+                .ok()?;
+            Some(Vec3::new(*v.first()?, *v.get(1)?, *v.get(2)?))
+        })
+        .unwrap_or_else(Vec3::zeros);
+    let illum = m.illumination_model.unwrap_or(2);
+
+    let transmissive = m.dissolve.is_some_and(|d| d < 1.0) || matches!(illum, 4 | 6 | 7);
+    let ior = transmissive.then(|| m.optical_density.filter(|v| *v > 0.0).unwrap_or(1.5));
+
+    let black = kd.is_some_and(|kd| kd.max() <= 0.0);
+    let mirror = (ks.max() > 0.0 && (matches!(illum, 3 | 5) || black)).then_some(ks);
+
+    imported_material(ke, ior, mirror, || {
+        // default diffuse colour from the mtl spec
+        let kd = kd.unwrap_or(Vec3::repeat(0.8));
+
+        // texture options such as -bm come before the file name
+        let name = m
+            .diffuse_texture
+            .as_ref()
+            .and_then(|t| t.split_whitespace().last());
+        let texture = name.and_then(|name| {
+            textures
+                .entry(name.to_owned())
+                .or_insert_with(|| {
+                    let path = dir.join(name);
+                    match Texture::open(&path) {
+                        Ok(t) => Some(Arc::new(t)),
+                        Err(e) => {
+                            log::warn!("failed to load texture {}: {e}", path.display());
+                            None
+                        }
+                    }
+                })
+                .clone()
+        });
+        (kd, texture)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    const OBJ: &str = "mtllib quads.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+usemtl textured
+f 1/1/1 2/2/1 3/3/1
+usemtl light
+f 1/1/1 3/3/1 4/4/1
+";
+
+    const MTL: &str = "newmtl textured
+Kd 1 1 1
+map_Kd -bm 1 checker.png
+newmtl light
+Kd 0 0 0
+Ke 1 0.5 0.25
+";
+
+    #[test]
+    fn load_mtl_materials() {
+        let dir = TempDir::new("load_mtl_materials");
+        let path = dir.write("quads.obj", OBJ);
+        dir.write("quads.mtl", MTL);
+        image::RgbImage::from_pixel(2, 2, image::Rgb([255, 0, 0]))
+            .save(dir.join("checker.png"))
+            .unwrap();
+
+        let mut materials = vec![Mat::Lambertian(Lambertian::new(0.5))];
+        let mesh = load_obj(
+            path.to_str().unwrap(),
+            0,
+            Some(&mut materials),
+            &ImportOptions::default(),
+        )
+        .unwrap();
+        // nothing is added when the mesh fails to load
+        let faceless = dir.write("faceless.obj", "mtllib quads.mtl\nv 0 0 0\nusemtl light\n");
+        let err = load_obj(
+            faceless.to_str().unwrap(),
+            0,
+            Some(&mut materials),
+            &ImportOptions::default(),
+        );
+
+        assert!(matches!(err, Err(LoadError::MissingAttribute("faces"))));
+        assert_eq!(materials.len(), 3);
+        // each usemtl group becomes a model with its own texture coordinates
+        assert_eq!(mesh.uvs.len(), 6);
+        let mut mats = mesh.triangles.iter().map(|t| t.mat).collect::<Vec<_>>();
+        mats.sort();
+        assert_eq!(mats, [1, 2]);
+
+        let Mat::Textured(textured) = &materials[1] else {
+            panic!("expected a textured material");
+        };
+        let int = Intersection::new(
+            1.0,
+            Vec3::zeros(),
+            Vec3::zeros(),
+            Vec3::z(),
+            Vec2::new(0.3, 0.6),
+            Vec3::x(),
+            Vec3::y(),
+            true,
+            1,
+        );
+        assert!(textured.albedo(&int, 700.0) > 0.9);
+        assert!(textured.albedo(&int, 450.0) < 0.1);
+
+        let Mat::SpectralPowerDistribution(light) = &materials[2] else {
+            panic!("expected an emissive material");
+        };
+        assert!(light.spectral_radiance(&int, Vec3::z(), 700.0) > 0.9);
+    }
+
+    #[test]
+    fn load_obj_errors_and_normals() {
+        let dir = TempDir::new("load_obj_errors");
+        let load = |name: &str, src: &str| {
+            let path = dir.write(name, src);
+            load_obj(path.to_str().unwrap(), 0, None, &ImportOptions::default())
+        };
+
+        let no_normals = load("no_normals.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n");
+        let bad_index = load(
+            "bad_index.obj",
+            "v 0 0 0\nv 1 0 0\nvn 0 0 1\nf 1//1 2//1 3//1\n",
+        );
+        let missing = load_obj(
+            dir.join("missing.obj").to_str().unwrap(),
+            0,
+            None,
+            &ImportOptions::default(),
+        );
+
+        assert_eq!(no_normals.unwrap().normals, [Vec3::z(); 3]);
+        // tobj rejects out of range face indices itself
+        assert!(matches!(bad_index, Err(LoadError::Parse(_))));
+        assert!(matches!(missing, Err(LoadError::Io(_))));
+
+        let mesh = tobj::Mesh {
+            positions: vec![0.0; 9],
+            normals: vec![0.0, 0.0, 1.0],
+            indices: vec![0, 1, 2],
+            normal_indices: vec![0, 1, 0],
+            ..Default::default()
+        };
+        let err = check_mesh_indices(&mesh).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "normal index 1 is out of range (expected less than 1)"
+        );
+    }
 }
@@ -0,0 +1,479 @@
+use crate::{
+    colour::srgb_to_linear,
+    load_error::{check_indices, LoadError},
+    mesh::{generate_normals, remove_degenerate, weld_vertices, ImportOptions},
+    prelude::*,
+};
+use std::{fs, str::SplitAsciiWhitespace};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Type {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Type {
+    fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "char" | "int8" => Type::I8,
+            "uchar" | "uint8" => Type::U8,
+            "short" | "int16" => Type::I16,
+            "ushort" | "uint16" => Type::U16,
+            "int" | "int32" => Type::I32,
+            "uint" | "uint32" => Type::U32,
+            "float" | "float32" => Type::F32,
+            "double" | "float64" => Type::F64,
+            _ => return Err(format!("unknown property type {name}")),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Type::I8 | Type::U8 => 1,
+            Type::I16 | Type::U16 => 2,
+            Type::I32 | Type::U32 | Type::F32 => 4,
+            Type::F64 => 8,
+        }
+    }
+
+    // full range of integer colour channels
+    fn max(self) -> f64 {
+        match self {
+            Type::I8 => i8::MAX as f64,
+            Type::U8 => u8::MAX as f64,
+            Type::I16 => i16::MAX as f64,
+            Type::U16 => u16::MAX as f64,
+            Type::I32 => i32::MAX as f64,
+            Type::U32 => u32::MAX as f64,
+            Type::F32 | Type::F64 => 1.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Property {
+    Scalar(String, Type),
+    List(String, Type, Type),
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+// the body of the file after the header
+enum Body<'a> {
+    Ascii(SplitAsciiWhitespace<'a>),
+    Binary(&'a [u8], bool),
+}
+
+impl Body<'_> {
+    fn read(&mut self, ty: Type) -> Result<f64, String> {
+        match self {
+            Body::Ascii(tokens) => {
+                let token = tokens.next().ok_or("unexpected end of file")?;
+                token
+                    .parse()
+                    .map_err(|_| format!("invalid {ty:?} value {token}"))
+            }
+            Body::Binary(bytes, little_endian) => {
+                if bytes.len() < ty.size() {
+                    return Err("unexpected end of file".into());
+                }
+                let (value, rest) = bytes.split_at(ty.size());
+                *bytes = rest;
+
+                let mut b = [0; 8];
+                b[..ty.size()].copy_from_slice(value);
+                if !*little_endian {
+                    b[..ty.size()].reverse();
+                }
+                Ok(match ty {
+                    Type::I8 => b[0] as i8 as f64,
+                    Type::U8 => b[0] as f64,
+                    Type::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+                    Type::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+                    Type::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Type::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Type::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Type::F64 => f64::from_le_bytes(b),
+                })
+            }
+        }
+    }
+
+    // list lengths & entries, which may be stored as any type
+    fn read_index(&mut self, ty: Type) -> Result<usize, String> {
+        let v = self.read(ty)?;
+        if v < 0.0 || v.fract() != 0.0 {
+            return Err(format!(
+                "list entries must be non-negative integers, got {v}"
+            ));
+        }
+        Ok(v as usize)
+    }
+}
+
+fn parse_header(src: &[u8]) -> Result<(Format, Vec<Element>, usize), String> {
+    if !src.starts_with(b"ply") {
+        return Err("missing ply magic number".into());
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        let end = src[offset..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .ok_or("missing end_header")?;
+        let line = std::str::from_utf8(&src[offset..offset + end])
+            .map_err(|_| "header is not valid utf-8")?;
+        offset += end + 1;
+
+        let mut words = line.split_ascii_whitespace();
+        match words.next() {
+            Some("format") => {
+                format = Some(match words.next() {
+                    Some("ascii") => Format::Ascii,
+                    Some("binary_little_endian") => Format::BinaryLittleEndian,
+                    Some("binary_big_endian") => Format::BinaryBigEndian,
+                    f => return Err(format!("unknown format {f:?}")),
+                })
+            }
+            Some("ply" | "comment" | "obj_info") | None => {}
+            Some("element") => {
+                let name = words.next().ok_or("element without a name")?;
+                let count = words
+                    .next()
+                    .and_then(|c| c.parse().ok())
+                    .ok_or("element without a count")?;
+                elements.push(Element {
+                    name: name.into(),
+                    count,
+                    properties: Vec::new(),
+                });
+            }
+            Some("property") => {
+                let element = elements
+                    .last_mut()
+                    .ok_or("property outside of an element")?;
+                let words = words.collect::<Vec<_>>();
+                let property = match words[..] {
+                    ["list", count, item, name] => {
+                        Property::List(name.into(), Type::parse(count)?, Type::parse(item)?)
+                    }
+                    [ty, name] => Property::Scalar(name.into(), Type::parse(ty)?),
+                    _ => return Err(format!("invalid property {line}")),
+                };
+                element.properties.push(property);
+            }
+            Some("end_header") => break,
+            Some(w) => return Err(format!("unexpected header line {w}")),
+        }
+    }
+
+    Ok((format.ok_or("missing format")?, elements, offset))
+}
+
+#[derive(Debug, Default)]
+struct PlyData {
+    vertices: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<Vec2>,
+    colours: Vec<Vec3>,
+    faces: Vec<Vec<usize>>,
+}
+
+fn parse_ply(src: &[u8]) -> Result<PlyData, LoadError> {
+    let (format, elements, offset) = parse_header(src).map_err(LoadError::Parse)?;
+    let mut body = match format {
+        Format::Ascii => Body::Ascii(
+            std::str::from_utf8(&src[offset..])
+                .map_err(|_| LoadError::Parse("ascii body is not valid utf-8".into()))?
+                .split_ascii_whitespace(),
+        ),
+        Format::BinaryLittleEndian => Body::Binary(&src[offset..], true),
+        Format::BinaryBigEndian => Body::Binary(&src[offset..], false),
+    };
+
+    let mut data = PlyData::default();
+    for element in &elements {
+        let names = element
+            .properties
+            .iter()
+            .map(|p| match p {
+                Property::Scalar(name, _) | Property::List(name, _, _) => name.as_str(),
+            })
+            .collect::<Vec<_>>();
+        let has = |options: &[&str]| names.iter().any(|n| options.contains(n));
+        let (normals, uvs, colours) = (
+            has(&["nx"]),
+            has(&["u", "s", "texture_u", "texture_s"]),
+            has(&["red"]),
+        );
+        if element.name == "vertex" && !["x", "y", "z"].iter().all(|p| names.contains(p)) {
+            return Err(LoadError::MissingAttribute("positions"));
+        }
+
+        for _ in 0..element.count {
+            let (mut p, mut n, mut uv, mut c) = ([0.0; 3], [0.0; 3], [0.0; 2], [1.0; 3]);
+            for property in &element.properties {
+                match property {
+                    Property::Scalar(name, ty) => {
+                        let v = body.read(*ty).map_err(LoadError::Parse)?;
+                        match name.as_str() {
+                            "x" => p[0] = v,
+                            "y" => p[1] = v,
+                            "z" => p[2] = v,
+                            "nx" => n[0] = v,
+                            "ny" => n[1] = v,
+                            "nz" => n[2] = v,
+                            "u" | "s" | "texture_u" | "texture_s" => uv[0] = v,
+                            "v" | "t" | "texture_v" | "texture_t" => uv[1] = v,
+                            "red" => c[0] = v / ty.max(),
+                            "green" => c[1] = v / ty.max(),
+                            "blue" => c[2] = v / ty.max(),
+                            _ => {}
+                        }
+                    }
+                    Property::List(name, count, item) => {
+                        let count = body.read_index(*count).map_err(LoadError::Parse)?;
+                        let indices = (0..count)
+                            .map(|_| body.read_index(*item))
+                            .collect::<Result<Vec<_>, _>>()
+                            .map_err(LoadError::Parse)?;
+                        if element.name == "face"
+                            && matches!(name.as_str(), "vertex_indices" | "vertex_index")
+                        {
+                            data.faces.push(indices);
+                        }
+                    }
+                }
+            }
+
+            if element.name == "vertex" {
+                data.vertices.push(p.map(|v| v as f32).into());
+                // zero normals are kept as is & replaced when loading
+                if normals {
+                    let n = Vec3::from(n.map(|v| v as f32));
+                    data.normals.push(n.try_normalize(0.0).unwrap_or_default());
+                }
+                if uvs {
+                    data.uvs.push(uv.map(|v| v as f32).into());
+                }
+                if colours {
+                    data.colours
+                        .push(c.map(|v| srgb_to_linear(v as f32)).into());
+                }
+            }
+        }
+    }
+
+    Ok(data)
+}
+
+// vertex attributes share their index, faces are triangulated as fans
+pub fn load_ply(path: &str, mat_index: usize, options: &ImportOptions) -> Result<Mesh, LoadError> {
+    let data = parse_ply(&fs::read(path)?)?;
+    let PlyData {
+        mut vertices,
+        mut normals,
+        uvs,
+        mut colours,
+        faces,
+    } = data;
+
+    let mut triangles = Vec::new();
+    for face in &faces {
+        for i in 1..face.len().saturating_sub(1) {
+            let pos = [face[0], face[i], face[i + 1]];
+            check_indices("vertex", pos, vertices.len())?;
+
+            // normals & uvs keep the original indices when vertices are welded
+            let triangle = Triangle::new(pos, pos, mat_index);
+            triangles.push(if uvs.is_empty() {
+                triangle
+            } else {
+                triangle.with_uv(pos)
+            });
+        }
+    }
+
+    // triangles with a zero normal are moved to the end & get generated ones like files without
+    let missing = if normals.is_empty() {
+        0..triangles.len()
+    } else {
+        let zero = |t: &Triangle| t.nor.iter().any(|&i| normals[i] == Vec3::zeros());
+        triangles.sort_by_key(zero);
+        triangles.partition_point(|t| !zero(t))..triangles.len()
+    };
+    if options.weld {
+        let welded = weld_vertices(&mut vertices, &mut colours, &mut triangles);
+        log::debug!("welded {welded} vertices");
+    }
+    generate_normals(
+        &vertices,
+        &mut triangles[missing],
+        &mut normals,
+        options.normals,
+    );
+    let removed = remove_degenerate(&vertices, &mut triangles);
+    if removed != 0 {
+        log::info!("removed {removed} degenerate triangles");
+    }
+
+    if triangles.is_empty() {
+        return Err(LoadError::MissingAttribute("faces"));
+    }
+
+    log::info!("loaded {} triangles", triangles.len());
+
+    let mesh = Mesh::new_with_uvs(vertices, normals, uvs, triangles);
+    Ok(if colours.is_empty() {
+        mesh
+    } else {
+        mesh.with_colours(colours)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    const HEADER: &str = "ply
+format {format} 1.0
+comment a quad with per-vertex colours
+element vertex 4
+property float x
+property float y
+property float z
+property float s
+property float t
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+";
+
+    const VERTICES: [[f32; 5]; 4] = [
+        [0.0, 0.0, 0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0, 1.0, 0.0],
+        [1.0, 1.0, 0.0, 1.0, 1.0],
+        [0.0, 1.0, 0.0, 0.0, 1.0],
+    ];
+
+    fn check(data: PlyData) {
+        assert_eq!(data.vertices.len(), 4);
+        assert_eq!(data.vertices[2], Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(data.uvs[1], Vec2::new(1.0, 0.0));
+        assert_eq!(data.colours[0], Vec3::new(1.0, 0.0, 0.0));
+        assert!(data.normals.is_empty());
+        assert_eq!(data.faces, [vec![0, 1, 2, 3]]);
+    }
+
+    #[test]
+    fn parse_ascii_and_binary() {
+        let mut ascii = HEADER.replace("{format}", "ascii");
+        for v in VERTICES {
+            ascii += &format!("{} {} {} {} {} 255 0 0\n", v[0], v[1], v[2], v[3], v[4]);
+        }
+        ascii += "4 0 1 2 3\n";
+        check(parse_ply(ascii.as_bytes()).unwrap());
+
+        for (format, le) in [("binary_little_endian", true), ("binary_big_endian", false)] {
+            let mut binary = HEADER.replace("{format}", format).into_bytes();
+            for v in VERTICES {
+                for f in v {
+                    binary.extend(if le { f.to_le_bytes() } else { f.to_be_bytes() });
+                }
+                binary.extend([255, 0, 0]);
+            }
+            binary.push(4);
+            for i in 0..4i32 {
+                binary.extend(if le { i.to_le_bytes() } else { i.to_be_bytes() });
+            }
+            check(parse_ply(&binary).unwrap());
+        }
+
+        let truncated = HEADER.replace("{format}", "ascii") + "0 0 0";
+        assert!(matches!(
+            parse_ply(truncated.as_bytes()),
+            Err(LoadError::Parse(_))
+        ));
+        let no_x = HEADER
+            .replace("{format}", "ascii")
+            .replace("property float x\n", "");
+        assert!(matches!(
+            parse_ply(no_x.as_bytes()),
+            Err(LoadError::MissingAttribute("positions"))
+        ));
+    }
+
+    #[test]
+    fn invalid_indices_and_normals() {
+        let mut ascii = HEADER.replace("{format}", "ascii");
+        for v in VERTICES {
+            ascii += &format!("{} {} {} {} {} 255 0 0\n", v[0], v[1], v[2], v[3], v[4]);
+        }
+        for face in ["4 0 1 -2 3", "4 0 1 2.5 3"] {
+            let err = parse_ply((ascii.clone() + face).as_bytes()).unwrap_err();
+            assert!(matches!(err, LoadError::Parse(_)), "{err}");
+        }
+
+        // the zero normal's triangle gets a generated one, the other keeps the file's
+        let src = "ply
+format ascii 1.0
+element vertex 4
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+element face 2
+property list uchar int vertex_indices
+end_header
+0 0 0 0 0 1
+1 0 0 0 0 1
+1 1 0 0 0 0
+0 1 0 0 0 1
+3 0 1 3
+3 1 2 3
+";
+        let dir = TempDir::new("ply_normals");
+        let path = dir.write("zero_normal.ply", src);
+        let options = ImportOptions {
+            weld: false,
+            ..Default::default()
+        };
+        let mesh = load_ply(path.to_str().unwrap(), 0, &options).unwrap();
+        assert!(mesh.normals.iter().all(|n| n.iter().all(|v| v.is_finite())));
+        for t in &mesh.triangles {
+            for i in t.nor {
+                assert_eq!(mesh.normals[i], Vec3::z());
+            }
+        }
+        let mut generated = mesh.triangles.iter().map(|t| t.nor.iter().all(|&i| i >= 4));
+        assert!(generated.any(|g| g) && mesh.triangles.iter().any(|t| t.nor == [0, 1, 3]));
+    }
+}
@@ -1,89 +1,242 @@
-#![feature(const_fn_floating_point_arithmetic)]
-
-mod camera;
-mod colour;
-mod cornell_box;
-mod integrator;
-mod load_obj;
-mod material;
-mod render;
-mod triangle;
-
-use crate::{cornell_box::cornell_box, prelude::*};
-use derive_new::new;
+use clap::{builder::RangedU64ValueParser, Parser};
 use fern::colors::{Color, ColoredLevelConfig};
 use minifb::*;
+use pathtracer::{
+    camera::Projection,
+    colour::WavelengthSampling,
+    cornell_box::cornell_box,
+    film::Filter,
+    integrator::DEFAULT_MAX_DEPTH,
+    prelude::*,
+    render::{self, ImageFormat, RenderSettings},
+    sampler::SamplerKind,
+    scene_file::{load_scene, CameraDesc},
+};
 
-pub type Vec3 = nalgebra::Vector3<f32>;
-pub type Ray = utility::Ray;
-pub type Vec2 = nalgebra::Vector2<f32>;
-pub type Bvh = bvh::Bvh;
+#[derive(Parser, Debug)]
+#[command(about = "Spectral path tracer")]
+struct Args {
+    /// built-in scene name (cornell), a TOML scene file or a glTF/GLB file
+    #[arg(short, long, default_value = "cornell")]
+    scene: String,
 
-pub const WIDTH: usize = 1080;
-pub const HEIGHT: usize = 1080;
+    /// image width in pixels
+    #[arg(short = 'W', long, default_value_t = 1080, value_parser = non_zero())]
+    width: usize,
 
-pub static mut VERTICES: Vec<Vec3> = vec![];
-pub static mut NORMALS: Vec<Vec3> = vec![];
-pub static mut MATERIALS: Vec<Mat> = vec![];
-pub static mut TRIANGLES: Vec<Triangle> = vec![];
+    /// image height in pixels
+    #[arg(short = 'H', long, default_value_t = 1080, value_parser = non_zero())]
+    height: usize,
 
-pub mod prelude {
-    pub use super::{
-        Bvh, Intersection, Ray, Vec2, Vec3, HEIGHT, MATERIALS, NORMALS, TRIANGLES, VERTICES, WIDTH,
-    };
-    pub use crate::{camera::Camera, material::*, triangle::Triangle};
-    pub use utility;
+    /// samples per pixel
+    #[arg(short = 'n', long, default_value_t = 1000, value_parser = non_zero())]
+    samples: usize,
+
+    /// maximum path depth
+    #[arg(short = 'd', long, default_value_t = DEFAULT_MAX_DEPTH)]
+    max_depth: u64,
+
+    /// output image path, only used when rendering headless
+    #[arg(short, long, default_value = "render.exr")]
+    output: String,
+
+    /// output image format (exr, hdr, png, jpeg), inferred from the output path if not given
+    #[arg(short, long)]
+    format: Option<ImageFormat>,
+
+    /// number of render threads, defaults to the number of logical cores
+    #[arg(short, long)]
+    threads: Option<usize>,
+
+    /// camera projection (perspective, orthographic, equirectangular, fisheye), overrides the scene's
+    #[arg(short, long)]
+    projection: Option<Projection>,
+
+    /// pixel reconstruction filter (box, tent, gaussian, mitchell, lanczos)
+    #[arg(long, default_value = "box")]
+    filter: Filter,
+
+    /// sample pattern (independent, stratified, halton, sobol)
+    #[arg(long, default_value = "sobol")]
+    sampler: SamplerKind,
+
+    /// seed of the sample pattern, renders with the same seed and settings are identical
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// wavelength distribution (uniform, visible), visible follows the colour matching functions
+    #[arg(long, default_value = "visible")]
+    wavelength_sampling: WavelengthSampling,
+
+    /// shutter open and close times, objects and cameras move from time 0 to 1 which spans a frame
+    #[arg(long, num_args = 2, value_names = ["OPEN", "CLOSE"], default_values_t = [0.0, 1.0])]
+    shutter: Vec<f32>,
+
+    /// render this inclusive range of frames of a keyframed scene headless, numbering the output
+    /// at its run of # characters or before its extension
+    #[arg(long, num_args = 2, value_names = ["FIRST", "LAST"])]
+    frames: Option<Vec<u32>>,
+
+    /// frames per second for keyframe times
+    #[arg(long, default_value_t = 24.0, value_parser = positive)]
+    fps: f32,
+
+    /// render to the output image without opening a window
+    #[arg(long)]
+    headless: bool,
+}
+
+fn non_zero() -> RangedU64ValueParser<usize> {
+    RangedU64ValueParser::new().range(1..)
 }
 
-#[derive(Debug, new)]
-pub struct Intersection {
-    pub t: f32,
-    pub pos: Vec3,
-    pub err: Vec3,
-    pub nor: Vec3,
-    pub out: bool,
-    pub mat: usize,
+// finite & greater than zero, which rules out nan
+fn positive(s: &str) -> std::result::Result<f32, String> {
+    let v = s.parse::<f32>().map_err(|e| e.to_string())?;
+    if v > 0.0 && v.is_finite() {
+        Ok(v)
+    } else {
+        Err(format!("{v} is not a positive number"))
+    }
 }
 
 fn main() {
     create_logger();
 
-    load_triangles();
+    let args = Args::parse();
+
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .unwrap();
+    }
+
+    let (mut scene, mut camera) = match args.scene.as_str() {
+        "cornell" => {
+            let mut scene = Scene::new();
+            load_triangles(&mut scene);
+            scene.build_bvh();
+
+            let camera = CameraDesc::new([0.0, -2.5, 0.0], [0.0, 0.0, 0.0], [0.0, 0.0, 1.0], 70.0);
+            (scene, camera)
+        }
+        path => match load_scene(path) {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("{e}");
+                std::process::exit(1);
+            }
+        },
+    };
+
+    let mut settings = RenderSettings::new(args.width, args.height, args.samples, args.max_depth);
+    if args.shutter[0] > args.shutter[1] {
+        log::error!("the shutter must open before it closes");
+        std::process::exit(1);
+    }
+    settings.shutter = [args.shutter[0], args.shutter[1]];
+    settings.filter = args.filter;
+    settings.sampler = args.sampler;
+    settings.seed = args.seed;
+    settings.wavelength_sampling = args.wavelength_sampling;
+    if args.frames.as_ref().is_some_and(|f| f[0] > f[1]) {
+        log::error!("frames must be in order");
+        std::process::exit(1);
+    }
 
-    let bvh = unsafe { Bvh::new(&mut TRIANGLES) };
+    if let Some(projection) = args.projection {
+        camera.projection = projection;
+    }
+    let frame_camera = |scene: &mut Scene, frame: u32| {
+        let start = frame as f32 / args.fps;
+        let end = (frame + 1) as f32 / args.fps;
+        scene.set_frame_interval(start, end);
+        match camera
+            .at_interval(start, end)
+            .build(settings.aspect_ratio())
+        {
+            Ok(camera) => camera,
+            Err(e) => {
+                log::error!("{e}");
+                std::process::exit(1);
+            }
+        }
+    };
 
-    let camera = Camera::new(
-        Vec3::new(0.0, -2.5, 0.0),
-        Vec3::new(0.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 1.0),
-        70.0,
-        1.0,
-        1.0,
-    );
-    let mut window = Window::new("path tracer", WIDTH, HEIGHT, WindowOptions::default()).unwrap();
-    window.limit_update_rate(Some(std::time::Duration::from_millis(16)));
+    if args.headless || args.frames.is_some() {
+        let Some(format) = args.format.or_else(|| ImageFormat::from_path(&args.output)) else {
+            log::error!("could not infer image format of {}", args.output);
+            std::process::exit(1);
+        };
 
-    render::render(&bvh, &camera, window, 1000);
+        let (frames, numbered) = match &args.frames {
+            Some(frames) => (frames[0]..=frames[1], true),
+            None => (0..=0, false),
+        };
+        for frame in frames {
+            let camera = frame_camera(&mut scene, frame);
+            let output = if numbered {
+                frame_path(&args.output, frame)
+            } else {
+                args.output.clone()
+            };
+
+            if let Err(e) =
+                render::render_no_window(&scene, camera.as_ref(), &settings, &output, format)
+            {
+                log::error!("failed to save {output}: {e}");
+                std::process::exit(1);
+            }
+            log::info!("saved {output}");
+        }
+    } else {
+        let camera = frame_camera(&mut scene, 0);
+        let mut window = Window::new(
+            "path tracer",
+            settings.width,
+            settings.height,
+            WindowOptions::default(),
+        )
+        .unwrap();
+        window.limit_update_rate(Some(std::time::Duration::from_millis(16)));
+
+        render::render(&scene, camera.as_ref(), window, &settings);
+    }
 }
 
-fn load_triangles() {
-    unsafe {
-        let no = NORMALS.len();
-        let mo = MATERIALS.len();
-        let vo = VERTICES.len();
+// replaces the last run of #s with the zero padded frame, or adds _0001 before the extension
+fn frame_path(output: &str, frame: u32) -> String {
+    if let Some(end) = output.rfind('#') {
+        let start = output[..end].trim_end_matches('#').len();
+        let width = end + 1 - start;
+        return format!("{}{frame:0width$}{}", &output[..start], &output[end + 1..]);
+    }
 
-        VERTICES.extend([
+    let path = std::path::Path::new(output);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let name = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{stem}_{frame:04}.{ext}"),
+        None => format!("{stem}_{frame:04}"),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+// triangle light inside the cornell box, the first white material is at index 1
+fn load_triangles(scene: &mut Scene) {
+    let mat = scene.add_material(Mat::SpectralPowerDistribution(
+        SpectralPowerDistribution::d65_illuminant(1e-3),
+    ));
+    scene.add_geometry_instance(Mesh::new(
+        vec![
             Vec3::new(0.0, 0.5, 0.999),
             Vec3::new(-0.5, -0.5, 0.999),
             Vec3::new(0.5, -0.5, 0.999),
-        ]);
-        NORMALS.extend([Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0)]);
-        MATERIALS.extend([Mat::SpectralPowerDistribution(
-            SpectralPowerDistribution::d65_illuminant(1e-3),
-        )]);
-        TRIANGLES.extend([Triangle::new([vo, 1 + vo, 2 + vo], [no, no, no], mo)]);
-        cornell_box(1.0);
-    }
+        ],
+        vec![Vec3::new(0.0, 0.0, 1.0)],
+        vec![Triangle::new([0, 1, 2], [0, 0, 0], mat)],
+    ));
+    cornell_box(scene, 1.0);
 }
 
 pub fn create_logger() {
@@ -109,3 +262,28 @@ pub fn create_logger() {
         .apply()
         .unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_paths() {
+        assert_eq!(frame_path("out/shot_###.png", 7), "out/shot_007.png");
+        // only the last run is replaced & frames wider than it aren't cut
+        assert_eq!(frame_path("v#/f##.exr", 123), "v#/f123.exr");
+        assert_eq!(frame_path("out/shot.png", 12), "out/shot_0012.png");
+        assert_eq!(frame_path("shot", 3), "shot_0003");
+    }
+
+    #[test]
+    fn argument_ranges() {
+        let parse = |args: &[&str]| Args::try_parse_from([&["pathtracer"], args].concat());
+        assert_eq!(parse(&[]).unwrap().samples, 1000);
+        assert!(parse(&["-n", "0"]).is_err());
+        for fps in ["0", "-24", "NaN", "inf"] {
+            assert!(parse(&["--fps", fps]).is_err(), "{fps}");
+        }
+        assert_eq!(parse(&["--fps", "30"]).unwrap().fps, 30.0);
+    }
+}
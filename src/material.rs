@@ -1,20 +1,31 @@
-use crate::prelude::*;
+use crate::{
+    prelude::*,
+    rgb_spectrum::{rgb_to_illuminant, rgb_to_reflectance, rgb_to_sigmoid, SigmoidPolynomial},
+    sampler::Sampler,
+    spectrum::Spectrum,
+};
 use derive_new::new;
-use rand::Rng;
-use std::unreachable;
+use std::{sync::Arc, unreachable};
 
-const MAX_WAVELENGTH: f32 = 750.0;
-const MIN_WAVELENGTH: f32 = 380.0;
+pub const MAX_WAVELENGTH: f32 = 750.0;
+pub const MIN_WAVELENGTH: f32 = 380.0;
 pub const WAVELENGTH_RANGE: f32 = MAX_WAVELENGTH - MIN_WAVELENGTH;
-const BINS: usize = 16;
-const INVERSE_INCREMENT: f32 = (BINS - 1) as f32 / WAVELENGTH_RANGE;
+pub const BINS: usize = 16;
+// averaged over each bin
+pub const D65: [f32; BINS] = [
+    49.43, 86.31, 85.99, 116.70, 115.40, 108.27, 107.54, 104.0, 96.12, 90.29, 85.84, 80.50, 80.92,
+    72.22, 66.23, 64.03,
+];
 
 #[derive(Debug, new)]
 pub enum Mat {
     SpectralPowerDistribution(SpectralPowerDistribution),
     SpectralReflectanceDistribution(SpectralReflectanceDistribution),
     SpectralRefract(SpectralRefract),
+    SpectralMirror(SpectralMirror),
     Lambertian(Lambertian),
+    Textured(Textured),
+    VertexColour(VertexColour),
 }
 
 impl Mat {
@@ -30,67 +41,73 @@ impl Mat {
         int: &Intersection,
         ray: &mut Ray,
         wavelength: f32,
-        rng: &mut impl Rng,
+        sampler: &mut dyn Sampler,
     ) -> bool {
+        if let Mat::SpectralPowerDistribution(_) = self {
+            return true;
+        }
+        // every bounce draws the same samples whether the material uses them or not, keeping the
+        // sampler's dimensions aligned between paths that hit different materials
+        let (u_dir, u_lobe) = (sampler.get_2d(), sampler.get_1d());
         match self {
-            Mat::SpectralPowerDistribution(_) => true,
-            Mat::Lambertian(_) | Mat::SpectralReflectanceDistribution(_) => {
-                Lambertian::scatter(int, ray, rng)
-            }
-            Mat::SpectralRefract(mat) => mat.scatter(int, ray, wavelength, rng),
+            Mat::SpectralPowerDistribution(_) => unreachable!(),
+            Mat::Lambertian(_)
+            | Mat::SpectralReflectanceDistribution(_)
+            | Mat::Textured(_)
+            | Mat::VertexColour(_) => Lambertian::scatter(int, ray, u_dir),
+            Mat::SpectralRefract(mat) => mat.scatter(int, ray, wavelength, u_lobe),
+            Mat::SpectralMirror(_) => SpectralMirror::scatter(int, ray),
         }
     }
 
-    pub fn eval_li_spdf(&self, _int: &Intersection, _wo: Vec3, _wi: Vec3, wavelength: f32) -> f32 {
+    pub fn eval_li_spdf(&self, int: &Intersection, _wo: Vec3, _wi: Vec3, wavelength: f32) -> f32 {
         match self {
-            Mat::SpectralPowerDistribution(_) => unreachable!(),
             Mat::Lambertian(l) => l.albedo,
             Mat::SpectralReflectanceDistribution(l) => l.albedo(wavelength),
-            Mat::SpectralRefract(_) => unreachable!(),
+            Mat::Textured(t) => t.albedo(int, wavelength),
+            Mat::VertexColour(v) => v.albedo(int, wavelength),
+            _ => unreachable!(),
         }
     }
 
-    pub fn eval_li(&self, _int: &Intersection, _wo: Vec3, _wi: Vec3, _: f32) -> f32 {
+    pub fn eval_li(&self, _int: &Intersection, _wo: Vec3, _wi: Vec3, wavelength: f32) -> f32 {
         match self {
             Mat::SpectralRefract(_) => 1.0,
+            Mat::SpectralMirror(m) => m.reflectance(wavelength),
             _ => unreachable!(),
         }
     }
     pub fn delta_dist(&self) -> bool {
-        matches!(self, Mat::SpectralRefract(_))
+        matches!(self, Mat::SpectralRefract(_) | Mat::SpectralMirror(_))
+    }
+
+    // scatters each wavelength in a different direction
+    pub fn dispersive(&self) -> bool {
+        matches!(self, Mat::SpectralRefract(mat) if mat.dispersive())
     }
 }
 
-// 380nm to 750nm
 #[derive(Debug)]
 pub struct SpectralPowerDistribution {
-    irradiance: [f32; BINS],
+    irradiance: Spectrum,
+    scale: f32,
 }
 
 impl SpectralPowerDistribution {
-    pub fn new(irradiance: [f32; BINS]) -> Self {
-        Self { irradiance }
+    pub fn new(irradiance: Spectrum) -> Self {
+        Self::new_with_scale(irradiance, 1.0)
     }
-    pub fn new_with_scale(mut irradiance: [f32; BINS], scale: f32) -> Self {
-        for bin in &mut irradiance {
-            *bin *= scale;
-        }
-        Self { irradiance }
+    pub fn new_with_scale(irradiance: Spectrum, scale: f32) -> Self {
+        Self { irradiance, scale }
     }
     pub fn d65_illuminant(scale: f32) -> Self {
-        let irradiance = [
-            49.43, 86.31, 85.99, 116.70, 115.40, 108.27, 107.54, 104.0, 96.12, 90.29, 85.84, 80.50,
-            80.92, 72.22, 66.23, 64.03,
-        ];
-        Self::new_with_scale(irradiance, scale)
+        Self::new_with_scale(Spectrum::from_bins(&D65), scale)
     }
 }
 
 impl SpectralPowerDistribution {
-    // asumme valid wavelength of from 380nm to 750nm
     pub fn spectral_radiance(&self, _: &Intersection, _: Vec3, wavelength: f32) -> f32 {
-        let index = ((wavelength - MIN_WAVELENGTH) * INVERSE_INCREMENT) as usize;
-        self.irradiance[index] // todo lerp
+        self.irradiance.eval(wavelength) * self.scale
     }
 }
 
@@ -100,114 +117,264 @@ pub struct Lambertian {
 }
 
 impl Lambertian {
-    pub fn scatter(int: &Intersection, ray: &mut Ray, rng: &mut impl Rng) -> bool {
-        *ray = Ray::new(
+    pub fn scatter(int: &Intersection, ray: &mut Ray, u: Vec2) -> bool {
+        *ray = Ray::new_with_time(
             int.pos
                 + Vec3::new(
                     int.nor.x * int.err.x,
                     int.nor.y * int.err.y,
                     int.nor.z * int.err.z,
                 ),
-            (random_in_unit_sphere(rng) + int.nor).normalize(),
+            cosine_direction(int.nor, u),
+            ray.time,
         );
         false
     }
 }
 
-fn random_vec3(rng: &mut impl Rng) -> Vec3 {
-    Vec3::new(
-        rng.gen::<f32>() - 0.5,
-        rng.gen::<f32>() - 0.5,
-        rng.gen::<f32>() - 0.5,
-    )
-}
-
-fn random_in_unit_sphere(rng: &mut impl Rng) -> Vec3 {
-    loop {
-        let p = random_vec3(rng);
-        if p.magnitude() < 1.0 {
-            return p;
-        }
-    }
+// a uniform direction on the unit sphere added to the normal is cosine distributed around it
+fn cosine_direction(nor: Vec3, u: Vec2) -> Vec3 {
+    let z = 1.0 - 2.0 * u.x;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = std::f32::consts::TAU * u.y;
+    let dir = nor + Vec3::new(r * phi.cos(), r * phi.sin(), z);
+    dir.try_normalize(1e-6).unwrap_or(nor)
 }
 
-// 380nm to 750nm
 #[derive(Debug)]
 pub struct SpectralReflectanceDistribution {
-    reflectance: [f32; BINS],
+    reflectance: Spectrum,
 }
 
 impl SpectralReflectanceDistribution {
-    pub fn new(reflectance: [f32; BINS]) -> Self {
-        for bin in &reflectance {
-            debug_assert!((0.0..1.0).contains(bin));
-        }
+    pub fn new(reflectance: Spectrum) -> Self {
+        debug_assert!(valid_reflectance(&reflectance));
         Self { reflectance }
     }
 }
 
 impl SpectralReflectanceDistribution {
-    // asumme valid wavelength of from 380nm to 750nm
     pub fn albedo(&self, wavelength: f32) -> f32 {
-        let index = ((wavelength - MIN_WAVELENGTH) * INVERSE_INCREMENT) as usize;
-        self.reflectance[index] // todo lerp
+        self.reflectance.eval(wavelength)
+    }
+}
+
+// energy conserving over the rendered wavelengths
+pub fn valid_reflectance(spectrum: &Spectrum) -> bool {
+    let (min, max) = spectrum.bounds();
+    min >= 0.0 && max < 1.0
+}
+
+// shared by the rgb based importers, emission wins over transmission, which wins over mirror
+// reflection and then diffuse, an optional texture replaces the diffuse reflectance
+pub fn imported_material(
+    emission: Vec3,
+    ior: Option<f32>,
+    mirror: Option<Vec3>,
+    diffuse: impl FnOnce() -> (Vec3, Option<Arc<Texture>>),
+) -> Mat {
+    if emission.max() > 0.0 {
+        return Mat::SpectralPowerDistribution(SpectralPowerDistribution::new(rgb_to_illuminant(
+            emission,
+        )));
+    }
+    if let Some(ior) = ior {
+        return Mat::SpectralRefract(SpectralRefract::new(Spectrum::Constant(ior)));
+    }
+    if let Some(mirror) = mirror {
+        return Mat::SpectralMirror(SpectralMirror::new(rgb_to_reflectance(mirror)));
+    }
+    match diffuse() {
+        (tint, Some(texture)) => Mat::Textured(Textured::new(texture, tint)),
+        (albedo, None) => Mat::SpectralReflectanceDistribution(
+            SpectralReflectanceDistribution::new(rgb_to_reflectance(albedo)),
+        ),
+    }
+}
+
+// diffuse albedo from an rgb texture, each texel is upsampled once & their spectra are filtered
+#[derive(Debug)]
+pub struct Textured {
+    texture: Arc<Texture>,
+    reflectances: Vec<SigmoidPolynomial>,
+}
+
+impl Textured {
+    pub fn new(texture: Arc<Texture>, tint: Vec3) -> Self {
+        let reflectances = texture
+            .texels()
+            .iter()
+            .map(|texel| rgb_to_sigmoid(texel.component_mul(&tint)))
+            .collect();
+        Self {
+            texture,
+            reflectances,
+        }
+    }
+
+    pub fn albedo(&self, int: &Intersection, wavelength: f32) -> f32 {
+        self.texture
+            .bilinear(int.uv)
+            .into_iter()
+            .map(|(i, w)| w * self.reflectances[i].eval(wavelength))
+            .sum()
+    }
+}
+
+// diffuse albedo from the mesh's vertex colours, surfaces without them use the fallback
+#[derive(Debug)]
+pub struct VertexColour {
+    fallback: SigmoidPolynomial,
+}
+
+impl VertexColour {
+    pub fn new(fallback: Vec3) -> Self {
+        Self {
+            fallback: rgb_to_sigmoid(fallback),
+        }
+    }
+
+    pub fn albedo(&self, int: &Intersection, wavelength: f32) -> f32 {
+        match int.colour {
+            Some(colour) => colour
+                .into_iter()
+                .map(|(reflectance, w)| w * reflectance.eval(wavelength))
+                .sum(),
+            None => self.fallback.eval(wavelength),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SpectralMirror {
+    reflectance: Spectrum,
+}
+
+impl SpectralMirror {
+    pub fn new(reflectance: Spectrum) -> Self {
+        debug_assert!(valid_reflectance(&reflectance));
+        Self { reflectance }
+    }
+
+    pub fn reflectance(&self, wavelength: f32) -> f32 {
+        self.reflectance.eval(wavelength)
+    }
+
+    pub fn scatter(int: &Intersection, ray: &mut Ray) -> bool {
+        let dir = utility::reflect_across_normal(-ray.dir, int.nor);
+        let origin = utility::offset_ray(int.pos, int.nor, int.err, true);
+        *ray = Ray::new_with_time(origin, dir, ray.time);
+        false
     }
 }
 
 #[derive(Debug)]
 pub struct SpectralRefract {
-    ior: [f32; BINS],
+    ior: Spectrum,
 }
 
 impl SpectralRefract {
-    pub fn new(ior: [f32; BINS]) -> Self {
-        for ior_wl in &ior {
-            debug_assert!(*ior_wl > 0.0);
-        }
+    pub fn new(ior: Spectrum) -> Self {
+        debug_assert!(ior.bounds().0 > 0.0);
         Self { ior }
     }
 
-    pub fn scatter(
-        &self,
-        int: &Intersection,
-        ray: &mut Ray,
-        wavelength: f32,
-        rng: &mut impl Rng,
-    ) -> bool {
-        let index = ((wavelength - MIN_WAVELENGTH) * INVERSE_INCREMENT) as usize;
-        let eta = self.ior[index];
+    pub fn ior(&self, wavelength: f32) -> f32 {
+        self.ior.eval(wavelength)
+    }
+
+    pub fn dispersive(&self) -> bool {
+        !self.ior.is_constant()
+    }
+
+    pub fn scatter(&self, int: &Intersection, ray: &mut Ray, wavelength: f32, u: f32) -> bool {
+        let eta = self.ior(wavelength);
         let mut eta_fraction = 1.0 / eta;
         if !int.out {
             eta_fraction = eta;
         }
 
-        let nwo = -ray.dir;
+        let nwo = -ray.dir.normalize();
 
         let cos_theta = (nwo.dot(&int.nor)).min(1.0);
 
-        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
-        let cannot_refract = eta_fraction * sin_theta > 1.0;
         let f0 = (1.0 - eta_fraction) / (1.0 + eta_fraction);
         let f0 = f0 * f0;
 
         let (origin, dir);
 
-        if cannot_refract || fresnel(cos_theta, f0) > rng.gen() {
-            // reflect
-            dir = utility::reflect_across_normal(nwo, int.nor);
-            origin = utility::offset_ray(int.pos, int.nor, int.err, true);
-        } else {
-            // refract
-            let perp = eta_fraction * (ray.dir + cos_theta * int.nor);
-            let para = -1.0 * (1.0 - perp.magnitude()).abs().sqrt() * int.nor;
-            dir = perp + para;
-            origin = utility::offset_ray(int.pos, int.nor, int.err, false);
+        match refract(-nwo, int.nor, eta_fraction) {
+            Some(refracted) if fresnel(cos_theta, f0) <= u => {
+                dir = refracted;
+                origin = utility::offset_ray(int.pos, int.nor, int.err, false);
+            }
+            _ => {
+                dir = utility::reflect_across_normal(nwo, int.nor);
+                origin = utility::offset_ray(int.pos, int.nor, int.err, true);
+            }
         }
-        *ray = Ray::new(origin, dir);
+        *ray = Ray::new_with_time(origin, dir, ray.time);
         false
     }
 }
 
+// snell's law for a unit direction & a normal facing against it, None on total internal reflection
+pub fn refract(dir: Vec3, nor: Vec3, eta_fraction: f32) -> Option<Vec3> {
+    let cos_theta = (-dir.dot(&nor)).min(1.0);
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+    if eta_fraction * sin_theta > 1.0 {
+        return None;
+    }
+
+    let perp = eta_fraction * (dir + cos_theta * nor);
+    let para = -(1.0 - perp.magnitude_squared()).abs().sqrt() * nor;
+    Some(perp + para)
+}
+
 pub fn fresnel(cos: f32, f0: f32) -> f32 {
     f0 + (1.0f32 - f0) * (1.0 - cos).powf(5.0)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cosine_directions() {
+        let nor = Vec3::new(1.0, 2.0, -0.5).normalize();
+        let mut mean_cos = 0.0;
+        for i in 0..64 {
+            let u = Vec2::new((i % 8) as f32 + 0.5, (i / 8) as f32 + 0.5) / 8.0;
+            let dir = cosine_direction(nor, u);
+            assert!((dir.magnitude() - 1.0).abs() < 1e-5 && dir.dot(&nor) >= 0.0);
+            mean_cos += dir.dot(&nor) / 64.0;
+        }
+        // the mean cosine of a cosine distribution is 2/3
+        assert!((mean_cos - 2.0 / 3.0).abs() < 1e-2, "{mean_cos}");
+    }
+
+    #[test]
+    fn textured_albedo() {
+        let (red, blue) = (Vec3::new(0.8, 0.1, 0.1), Vec3::new(0.1, 0.1, 0.8));
+        let texture = Arc::new(Texture::new(2, 1, vec![red, blue]));
+        let textured = Textured::new(texture, Vec3::repeat(0.5));
+        let mut int = Intersection::new(
+            1.0,
+            Vec3::zeros(),
+            Vec3::zeros(),
+            Vec3::z(),
+            Vec2::new(0.25, 0.5),
+            Vec3::x(),
+            Vec3::y(),
+            true,
+            0,
+        );
+        let (tinted_red, tinted_blue) = (rgb_to_sigmoid(red * 0.5), rgb_to_sigmoid(blue * 0.5));
+        assert_eq!(textured.albedo(&int, 650.0), tinted_red.eval(650.0));
+
+        // between texel centres the spectra are blended
+        int.uv.x = 0.5;
+        let blended = 0.5 * (tinted_red.eval(450.0) + tinted_blue.eval(450.0));
+        assert!((textured.albedo(&int, 450.0) - blended).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,255 @@
+use crate::{
+    prelude::*,
+    rgb_spectrum::{rgb_to_sigmoid, SigmoidPolynomial},
+};
+use bvh::aabb::Aabb;
+use std::collections::HashMap;
+
+// triangles in object space along with their own bvh, placed in a scene through instances
+#[derive(Debug)]
+pub struct Mesh {
+    pub vertices: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<Vec2>,
+    // linear rgb per vertex, either empty or one for each vertex
+    pub colours: Vec<Vec3>,
+    // the colours upsampled once up front rather than at every hit
+    pub reflectances: Vec<SigmoidPolynomial>,
+    pub triangles: Vec<Triangle>,
+    pub bvh: Bvh,
+    pub bounds: Aabb,
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Vec3>, normals: Vec<Vec3>, triangles: Vec<Triangle>) -> Self {
+        Self::new_with_uvs(vertices, normals, Vec::new(), triangles)
+    }
+
+    // reorders triangles
+    pub fn new_with_uvs(
+        vertices: Vec<Vec3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<Vec2>,
+        mut triangles: Vec<Triangle>,
+    ) -> Self {
+        assert!(
+            !triangles.is_empty(),
+            "mesh must contain at least one triangle"
+        );
+
+        let bounds = triangles
+            .iter()
+            .map(|t| t.aabb(&vertices))
+            .collect::<Vec<_>>();
+
+        let bvh = Bvh::new_with_bounds(&mut triangles, &bounds);
+        let bounds = bounds.into_iter().reduce(Aabb::merge).unwrap();
+
+        Self {
+            vertices,
+            normals,
+            uvs,
+            colours: Vec::new(),
+            reflectances: Vec::new(),
+            triangles,
+            bvh,
+            bounds,
+        }
+    }
+
+    pub fn with_colours(mut self, colours: Vec<Vec3>) -> Self {
+        assert_eq!(colours.len(), self.vertices.len());
+        self.reflectances = colours.iter().copied().map(rgb_to_sigmoid).collect();
+        self.colours = colours;
+        self
+    }
+
+    pub fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        self.bvh
+            .traverse(ray)
+            .into_iter()
+            .flat_map(|r| &self.triangles[r])
+            .filter_map(|v| v.intersect(ray, self))
+            .filter(|v| v.t > 0.0)
+            .min_by(|a, b| utility::float_cmp(a.t, b.t))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NormalGeneration {
+    Flat,
+    // faces only share a normal if theirs differ by at most the crease angle in degrees
+    Smooth { crease_angle: f32 },
+}
+
+// used by the mesh loaders, normals are only generated for files without them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImportOptions {
+    pub normals: NormalGeneration,
+    pub weld: bool,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            normals: NormalGeneration::Smooth { crease_angle: 60.0 },
+            weld: true,
+        }
+    }
+}
+
+// merges vertices with identical positions (and colours if present), returns the number removed
+pub fn weld_vertices(
+    vertices: &mut Vec<Vec3>,
+    colours: &mut Vec<Vec3>,
+    triangles: &mut [Triangle],
+) -> usize {
+    // adding zero folds -0.0 into 0.0
+    let bits = |v: Vec3| v.map(|x| (x + 0.0).to_bits());
+
+    let (mut welded, mut welded_colours) = (Vec::new(), Vec::new());
+    let mut unique = HashMap::new();
+    let remap = vertices
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            let colour = colours.get(i).copied();
+            *unique
+                .entry((bits(*v), colour.map(bits)))
+                .or_insert_with(|| {
+                    welded.push(*v);
+                    welded_colours.extend(colour);
+                    welded.len() - 1
+                })
+        })
+        .collect::<Vec<_>>();
+
+    for t in triangles {
+        t.pos = t.pos.map(|i| remap[i]);
+    }
+
+    let removed = vertices.len() - welded.len();
+    *vertices = welded;
+    *colours = welded_colours;
+    removed
+}
+
+// zero area triangles can't be hit but still take up space in the bvh
+pub fn remove_degenerate(vertices: &[Vec3], triangles: &mut Vec<Triangle>) -> usize {
+    let len = triangles.len();
+    triangles.retain(|t| face_normal(vertices, t).magnitude_squared() > 0.0);
+    len - triangles.len()
+}
+
+// not normalised, the magnitude is twice the area
+fn face_normal(vertices: &[Vec3], t: &Triangle) -> Vec3 {
+    let [a, b, c] = t.pos.map(|i| vertices[i]);
+    (b - a).cross(&(c - a))
+}
+
+// appends the new normals and points the triangles at them
+pub fn generate_normals(
+    vertices: &[Vec3],
+    triangles: &mut [Triangle],
+    normals: &mut Vec<Vec3>,
+    mode: NormalGeneration,
+) {
+    let faces = triangles
+        .iter()
+        .map(|t| {
+            face_normal(vertices, t)
+                .try_normalize(0.0)
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+
+    let crease_angle = match mode {
+        NormalGeneration::Flat => {
+            for (t, n) in triangles.iter_mut().zip(faces) {
+                normals.push(n);
+                t.nor = [normals.len() - 1; 3];
+            }
+            return;
+        }
+        NormalGeneration::Smooth { crease_angle } => crease_angle,
+    };
+    let min_cos = crease_angle.to_radians().cos();
+
+    // faces around each vertex along with their interior angle there
+    let mut adjacent = vec![Vec::new(); vertices.len()];
+    for (f, t) in triangles.iter().enumerate() {
+        let p = t.pos.map(|i| vertices[i]);
+        for k in 0..3 {
+            let e0 = p[(k + 1) % 3] - p[k];
+            let e1 = p[(k + 2) % 3] - p[k];
+            adjacent[t.pos[k]].push((f, e0.angle(&e1)));
+        }
+    }
+
+    // corners with the same vertex & normal share an entry
+    let mut unique = HashMap::new();
+    for (f, t) in triangles.iter_mut().enumerate() {
+        t.nor = t.pos.map(|v| {
+            let n = adjacent[v]
+                .iter()
+                .filter(|(g, _)| faces[f].dot(&faces[*g]) >= min_cos)
+                .map(|(g, angle)| *angle * faces[*g])
+                .sum::<Vec3>()
+                .try_normalize(0.0)
+                .unwrap_or(faces[f]);
+
+            *unique.entry((v, n.map(f32::to_bits))).or_insert_with(|| {
+                normals.push(n);
+                normals.len() - 1
+            })
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weld_and_generate_normals() {
+        // two faces meeting at a right angle along the x axis, with the edge duplicated
+        let mut vertices = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(-0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 0.0),
+        ];
+        let mut triangles = vec![
+            Triangle::new([0, 1, 2], [0; 3], 0),
+            Triangle::new([3, 4, 5], [0; 3], 0),
+            Triangle::new([0, 1, 1], [0; 3], 0),
+        ];
+
+        assert_eq!(
+            weld_vertices(&mut vertices, &mut Vec::new(), &mut triangles),
+            2
+        );
+        assert_eq!(triangles[1].pos, [0, 3, 1]);
+        assert_eq!(remove_degenerate(&vertices, &mut triangles), 1);
+
+        let shared = |crease_angle| {
+            let mut normals = Vec::new();
+            let mut triangles = triangles
+                .iter()
+                .map(|t| Triangle::new(t.pos, t.nor, 0))
+                .collect::<Vec<_>>();
+            generate_normals(
+                &vertices,
+                &mut triangles,
+                &mut normals,
+                NormalGeneration::Smooth { crease_angle },
+            );
+            normals[triangles[0].nor[0]]
+        };
+        assert_eq!(shared(60.0), Vec3::z());
+        let n = Vec3::new(0.0, 1.0, 1.0).normalize();
+        assert!((shared(100.0) - n).magnitude() < 1e-6);
+    }
+}
@@ -0,0 +1,69 @@
+use crate::{
+    prelude::*,
+    shapes::{Cylinder, Disk, Quad, Sphere},
+};
+use bvh::aabb::{Aabb, Aabound};
+
+// anything that can be placed in a scene, bounds and intersections are in object space
+pub trait Primitive: Aabound {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection>;
+}
+
+impl Aabound for Mesh {
+    fn aabb(&self) -> Aabb {
+        self.bounds
+    }
+}
+
+impl Primitive for Mesh {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        Mesh::intersect(self, ray)
+    }
+}
+
+#[derive(Debug)]
+pub enum Geometry {
+    Mesh(Mesh),
+    Sphere(Sphere),
+    Disk(Disk),
+    Quad(Quad),
+    Cylinder(Cylinder),
+}
+
+impl Aabound for Geometry {
+    fn aabb(&self) -> Aabb {
+        match self {
+            Geometry::Mesh(v) => v.aabb(),
+            Geometry::Sphere(v) => v.aabb(),
+            Geometry::Disk(v) => v.aabb(),
+            Geometry::Quad(v) => v.aabb(),
+            Geometry::Cylinder(v) => v.aabb(),
+        }
+    }
+}
+
+impl Primitive for Geometry {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        match self {
+            Geometry::Mesh(v) => v.intersect(ray),
+            Geometry::Sphere(v) => v.intersect(ray),
+            Geometry::Disk(v) => v.intersect(ray),
+            Geometry::Quad(v) => v.intersect(ray),
+            Geometry::Cylinder(v) => v.intersect(ray),
+        }
+    }
+}
+
+macro_rules! impl_from_geometry {
+    ($($variant:ident),*) => {
+        $(
+            impl From<$variant> for Geometry {
+                fn from(v: $variant) -> Self {
+                    Geometry::$variant(v)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_geometry!(Mesh, Sphere, Disk, Quad, Cylinder);
@@ -1,22 +1,84 @@
 use crate::{
-    colour::{inverse_pdf_wl, sample_wl, to_u32, x_bar, xyz_to_rgb, y_bar, z_bar},
-    integrator::NaiveSpectral,
+    camera::CameraSample,
+    colour::{to_rgb8, to_u32, SampledWavelengths, WavelengthSampling, HERO_WAVELENGTHS},
+    film::{Film, FilmSample, Filter},
+    integrator::{NaiveSpectral, DEFAULT_MAX_DEPTH},
     prelude::*,
+    sampler::SamplerKind,
 };
+use derive_new::new;
+use image::codecs::hdr::HdrEncoder;
 use indicatif::{ProgressBar, ProgressStyle};
 use minifb::{Key, Window};
-use rand::thread_rng;
 use rayon::prelude::*;
+use std::{fs::File, io::BufWriter, path::Path, str::FromStr};
+
+#[derive(Debug, Clone, new)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    pub samples: usize,
+    pub max_depth: u64,
+    // open & close times, motion goes from 0 to 1
+    #[new(value = "[0.0, 1.0]")]
+    pub shutter: [f32; 2],
+    #[new(default)]
+    pub filter: Filter,
+    #[new(default)]
+    pub sampler: SamplerKind,
+    #[new(default)]
+    pub seed: u64,
+    #[new(default)]
+    pub wavelength_sampling: WavelengthSampling,
+}
 
-pub fn render(bvh: &Bvh, cam: &Camera, mut window: Window, max_samples: usize) {
-    let mut screen_buffer = vec![0u32; WIDTH * HEIGHT];
+impl RenderSettings {
+    pub fn aspect_ratio(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+}
 
-    let (render_buffer, present_buffer) = (
-        std::sync::Mutex::new(vec![Vec3::new(0.0, 0.0, 0.0); WIDTH * HEIGHT]),
-        std::sync::Mutex::new(vec![Vec3::zeros(); WIDTH * HEIGHT]),
-    );
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self::new(1080, 1080, 1000, DEFAULT_MAX_DEPTH)
+    }
+}
 
-    let chunk_size = 10_000usize;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Exr,
+    Hdr,
+    Png,
+    Jpeg,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        path.as_ref().extension()?.to_str()?.parse().ok()
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "exr" => Ok(Self::Exr),
+            "hdr" => Ok(Self::Hdr),
+            "png" => Ok(Self::Png),
+            "jpg" | "jpeg" => Ok(Self::Jpeg),
+            _ => Err(format!(
+                "unknown image format \"{s}\", expected one of exr, hdr, png, jpeg"
+            )),
+        }
+    }
+}
+
+pub fn render(scene: &Scene, cam: &dyn Camera, mut window: Window, settings: &RenderSettings) {
+    let (width, height, max_samples) = (settings.width, settings.height, settings.samples);
+    let mut screen_buffer = vec![0u32; width * height];
+    let mut samples = vec![FilmSample::default(); width * height];
+    let mut film = Film::new(width, height, settings.filter);
 
     let bar = ProgressBar::new(max_samples as u64).with_style(
         ProgressStyle::default_bar()
@@ -29,7 +91,7 @@ pub fn render(bvh: &Bvh, cam: &Camera, mut window: Window, max_samples: usize) {
             State::ReRender => {
                 // investigate why this causes a memory leak
                 bar.finish_and_clear();
-                return render(bvh, cam, window, max_samples);
+                return render(scene, cam, window, settings);
             }
             State::Exit => break,
             State::Continue => {}
@@ -37,45 +99,8 @@ pub fn render(bvh: &Bvh, cam: &Camera, mut window: Window, max_samples: usize) {
 
         {
             let start = std::time::Instant::now();
-            let mut rbuffer = render_buffer.lock().unwrap();
-
-            let frame_ray_count: u64 = rbuffer
-                .par_chunks_mut(chunk_size)
-                .enumerate()
-                .map(|(chunk_i, chunk)| {
-                    let mut chunk_ray_count = 0;
-                    let chunk_offset = chunk_size * chunk_i;
-                    for (pixel_i, pixel) in chunk.iter_mut().enumerate() {
-                        let pixel_i = chunk_offset + pixel_i;
-                        let (u, v) = (pixel_i % WIDTH, pixel_i / WIDTH);
-                        let (u, v) = (
-                            u as f32 / (WIDTH - 1) as f32,
-                            v as f32 / (HEIGHT - 1) as f32,
-                        );
-                        let mut ray = cam.get_ray(u, v);
-                        let mut rng = thread_rng();
-                        let wavelength = sample_wl(&mut rng);
-                        let (radiance, ray_count) =
-                            NaiveSpectral::radiance(&mut ray, bvh, wavelength, &mut rng);
-
-                        if radiance != 0.0 {
-                            let radiance = radiance * inverse_pdf_wl(wavelength);
-                            let xyz =
-                                Vec3::new(x_bar(wavelength), y_bar(wavelength), z_bar(wavelength))
-                                    * radiance;
-                            let col = xyz_to_rgb(xyz);
-
-                            *pixel += (col - *pixel) / (sample + 1) as f32;
-                        } else {
-                            *pixel += (Vec3::zeros() - *pixel) / (sample + 1) as f32;
-                        }
-
-                        chunk_ray_count += ray_count;
-                    }
-                    chunk_ray_count
-                })
-                .sum();
-
+            let frame_ray_count = sample_pass(scene, cam, settings, sample as u32, &mut samples);
+            film.add_samples(&samples);
             let dur = start.elapsed();
 
             bar.set_position(sample as u64);
@@ -86,17 +111,14 @@ pub fn render(bvh: &Bvh, cam: &Camera, mut window: Window, max_samples: usize) {
             ));
         }
 
-        let mut rbuf = render_buffer.lock().unwrap();
-        let mut pbuf = present_buffer.lock().unwrap();
-
-        std::mem::swap(&mut rbuf, &mut pbuf);
+        let image = film.image();
         screen_buffer
             .par_iter_mut()
             .enumerate()
-            .for_each(|(i, v)| *v = to_u32(pbuf[i]));
+            .for_each(|(i, v)| *v = to_u32(image[i]));
 
         window
-            .update_with_buffer(&screen_buffer, WIDTH, HEIGHT)
+            .update_with_buffer(&screen_buffer, width, height)
             .unwrap();
     }
 
@@ -105,11 +127,16 @@ pub fn render(bvh: &Bvh, cam: &Camera, mut window: Window, max_samples: usize) {
     while window.is_open() && !window.is_key_down(Key::Escape) {}
 }
 
-#[allow(dead_code)]
-pub fn render_no_window(bvh: &Bvh, cam: &Camera, max_samples: usize, filename: &str) {
-    let mut render_buffer = vec![Vec3::new(0.0, 0.0, 0.0); WIDTH * HEIGHT];
-
-    let chunk_size = 10_000usize;
+pub fn render_no_window(
+    scene: &Scene,
+    cam: &dyn Camera,
+    settings: &RenderSettings,
+    filename: &str,
+    format: ImageFormat,
+) -> image::ImageResult<()> {
+    let (width, height, max_samples) = (settings.width, settings.height, settings.samples);
+    let mut samples = vec![FilmSample::default(); width * height];
+    let mut film = Film::new(width, height, settings.filter);
 
     let bar = ProgressBar::new(max_samples as u64).with_style(
         ProgressStyle::default_bar()
@@ -118,71 +145,128 @@ pub fn render_no_window(bvh: &Bvh, cam: &Camera, max_samples: usize, filename: &
     );
 
     for sample in 0..max_samples {
-        {
-            let start = std::time::Instant::now();
-            let rbuffer = &mut render_buffer;
-
-            let frame_ray_count: u64 = rbuffer
-                .par_chunks_mut(chunk_size)
-                .enumerate()
-                .map(|(chunk_i, chunk)| {
-                    let mut chunk_ray_count = 0;
-                    let chunk_offset = chunk_size * chunk_i;
-                    for (pixel_i, pixel) in chunk.iter_mut().enumerate() {
-                        let pixel_i = chunk_offset + pixel_i;
-                        let (u, v) = (pixel_i % WIDTH, pixel_i / WIDTH);
-                        let (u, v) = (
-                            u as f32 / (WIDTH - 1) as f32,
-                            v as f32 / (HEIGHT - 1) as f32,
-                        );
-                        let mut ray = cam.get_ray(u, v);
-                        let mut rng = thread_rng();
-                        let wavelength = sample_wl(&mut rng);
-                        let (radiance, ray_count) =
-                            NaiveSpectral::radiance(&mut ray, bvh, wavelength, &mut rng);
-
-                        if radiance != 0.0 {
-                            let radiance = radiance * inverse_pdf_wl(wavelength);
-                            let xyz =
-                                Vec3::new(x_bar(wavelength), y_bar(wavelength), z_bar(wavelength))
-                                    * radiance;
-                            let col = xyz_to_rgb(xyz);
-
-                            *pixel += (col - *pixel) / (sample + 1) as f32;
-                        } else {
-                            *pixel += (Vec3::zeros() - *pixel) / (sample + 1) as f32;
-                        }
-
-                        chunk_ray_count += ray_count;
-                    }
-                    chunk_ray_count
-                })
-                .sum();
+        let start = std::time::Instant::now();
+        let frame_ray_count = sample_pass(scene, cam, settings, sample as u32, &mut samples);
+        film.add_samples(&samples);
+        let dur = start.elapsed();
+
+        bar.set_position(sample as u64);
+        bar.set_message(format!(
+            "{:.2} MRay/s ({})",
+            frame_ray_count as f64 * 0.000001 / dur.as_secs_f64(),
+            dur.as_millis()
+        ));
+    }
 
-            let dur = start.elapsed();
+    bar.finish_and_clear();
 
-            bar.set_position(sample as u64);
-            bar.set_message(format!(
-                "{:.2} MRay/s ({})",
-                frame_ray_count as f64 * 0.000001 / dur.as_secs_f64(),
-                dur.as_millis()
-            ));
-        }
-    }
+    save_image(&film.image(), width, height, filename, format)
+}
 
-    let img = image::Rgb32FImage::from_vec(
-        WIDTH as u32,
-        HEIGHT as u32,
-        render_buffer
-            .iter()
-            .flat_map(|v| [v.x, v.y, v.z])
-            .collect::<Vec<f32>>(),
-    )
-    .unwrap();
+// one jittered sample per pixel, returns the number of rays traced
+fn sample_pass(
+    scene: &Scene,
+    cam: &dyn Camera,
+    settings: &RenderSettings,
+    index: u32,
+    samples: &mut [FilmSample],
+) -> u64 {
+    let (width, height) = (settings.width, settings.height);
+    let chunk_size = 10_000usize;
 
-    img.save(filename).unwrap();
+    samples
+        .par_chunks_mut(chunk_size)
+        .enumerate()
+        .map(|(chunk_i, chunk)| {
+            let mut chunk_ray_count = 0;
+            let chunk_offset = chunk_size * chunk_i;
+            let mut sampler = settings.sampler.build(settings.seed, settings.samples);
+            for (pixel_i, sample) in chunk.iter_mut().enumerate() {
+                let pixel_i = chunk_offset + pixel_i;
+                let (x, y) = (pixel_i % width, pixel_i / width);
+                sampler.start_pixel_sample([x as u32, y as u32], index);
+
+                let offset = sampler.get_2d();
+                let film = Vec2::new(
+                    (x as f32 + offset.x) / width as f32,
+                    (y as f32 + offset.y) / height as f32,
+                );
+                let mut wavelengths =
+                    SampledWavelengths::sample(sampler.get_1d(), settings.wavelength_sampling);
+                if cam.dispersive() {
+                    wavelengths.terminate_secondary();
+                }
+                let [open, close] = settings.shutter;
+                let time = open + sampler.get_1d() * (close - open);
+                let ray = cam.get_ray(&CameraSample::new(
+                    film,
+                    sampler.get_2d(),
+                    time,
+                    wavelengths.hero(),
+                ));
+                let (radiance, ray_count) = match ray {
+                    Some(mut ray) => NaiveSpectral::radiance(
+                        &mut ray,
+                        scene,
+                        &mut wavelengths,
+                        settings.max_depth,
+                        sampler.as_mut(),
+                    ),
+                    None => ([0.0; HERO_WAVELENGTHS], 0),
+                };
+
+                let col = wavelengths.to_rgb(radiance);
+                *sample = FilmSample { offset, col };
+
+                chunk_ray_count += ray_count;
+            }
+            chunk_ray_count
+        })
+        .sum()
+}
 
-    bar.finish_and_clear();
+pub fn save_image(
+    buffer: &[Vec3],
+    width: usize,
+    height: usize,
+    filename: &str,
+    format: ImageFormat,
+) -> image::ImageResult<()> {
+    assert_eq!(buffer.len(), width * height);
+
+    match format {
+        ImageFormat::Exr => {
+            let img = image::Rgb32FImage::from_vec(
+                width as u32,
+                height as u32,
+                buffer.iter().flat_map(|v| [v.x, v.y, v.z]).collect(),
+            )
+            .unwrap();
+            img.save_with_format(filename, image::ImageFormat::OpenExr)
+        }
+        ImageFormat::Hdr => {
+            let pixels = buffer
+                .iter()
+                .map(|v| image::Rgb([v.x, v.y, v.z]))
+                .collect::<Vec<_>>();
+            let file = BufWriter::new(File::create(filename)?);
+            HdrEncoder::new(file).encode(&pixels, width, height)
+        }
+        ImageFormat::Png | ImageFormat::Jpeg => {
+            let img = image::RgbImage::from_vec(
+                width as u32,
+                height as u32,
+                buffer.iter().flat_map(|v| to_rgb8(*v)).collect(),
+            )
+            .unwrap();
+            let format = if format == ImageFormat::Png {
+                image::ImageFormat::Png
+            } else {
+                image::ImageFormat::Jpeg
+            };
+            img.save_with_format(filename, format)
+        }
+    }
 }
 
 enum State {
@@ -0,0 +1,218 @@
+use crate::{
+    colour::y_bar,
+    material::{D65, MIN_WAVELENGTH, WAVELENGTH_RANGE},
+    prelude::*,
+};
+
+// jakob & hanika 2019, smooth spectra bounded by (0, 1) fitted to linear srgb under d65
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SigmoidPolynomial {
+    // quadratic in the wavelength normalised to [0, 1] over the rendered range
+    c: [f32; 3],
+}
+
+impl SigmoidPolynomial {
+    pub fn eval(&self, wavelength: f32) -> f32 {
+        let t = (wavelength - MIN_WAVELENGTH) / WAVELENGTH_RANGE;
+        sigmoid((self.c[0] * t + self.c[1]) * t + self.c[2])
+    }
+
+    // smallest & largest values over the rendered wavelengths
+    pub fn bounds(&self) -> (f32, f32) {
+        let [a, b, _] = self.c;
+        let mut ts = vec![0.0, 1.0];
+        if a != 0.0 {
+            ts.push((-b / (2.0 * a)).clamp(0.0, 1.0));
+        }
+        ts.into_iter()
+            .map(|t| self.eval(MIN_WAVELENGTH + t * WAVELENGTH_RANGE))
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), v| {
+                (min.min(v), max.max(v))
+            })
+    }
+
+    pub fn is_constant(&self) -> bool {
+        self.c[0] == 0.0 && self.c[1] == 0.0
+    }
+}
+
+fn sigmoid(x: f32) -> f32 {
+    if x.is_infinite() {
+        return if x > 0.0 { 1.0 } else { 0.0 };
+    }
+    0.5 + x / (2.0 * (1.0 + x * x).sqrt())
+}
+
+// brightest reflectance, keeps the sigmoid finite so reflectances stay below 1
+const MAX_REFLECTANCE: f32 = 0.9999;
+
+// linear rgb clamped to a valid reflectance
+pub fn rgb_to_sigmoid(rgb: Vec3) -> SigmoidPolynomial {
+    let rgb = rgb.map(|v| v.clamp(0.0, MAX_REFLECTANCE));
+    // greys don't need the table
+    if rgb.x == rgb.y && rgb.y == rgb.z {
+        let v = rgb.x;
+        let c = if v == 0.0 {
+            f32::NEG_INFINITY
+        } else {
+            (v - 0.5) / (v * (1.0 - v)).sqrt()
+        };
+        return SigmoidPolynomial { c: [0.0, 0.0, c] };
+    }
+    SigmoidPolynomial { c: lookup(rgb) }
+}
+
+pub fn rgb_to_reflectance(rgb: Vec3) -> Spectrum {
+    Spectrum::Sigmoid(rgb_to_sigmoid(rgb))
+}
+
+// d65 tinted by a sigmoid, unbounded rgb keeps the luminance a flat spectrum of the same value
+// would have so white is as bright as before & only the hue follows the sigmoid
+pub fn rgb_to_illuminant(rgb: Vec3) -> Spectrum {
+    let rgb = rgb.map(|v| v.max(0.0));
+    let scale = 2.0 * rgb.max();
+    if scale == 0.0 {
+        return Spectrum::Constant(0.0);
+    }
+    let tint = rgb_to_sigmoid(rgb / scale);
+    let norm = d65_luminance_ratio();
+    let d65 = Spectrum::from_bins(&D65);
+
+    let samples = (0..=SAMPLES)
+        .map(|i| {
+            let wl = MIN_WAVELENGTH + i as f32 * WAVELENGTH_RANGE / SAMPLES as f32;
+            (wl, scale * norm * tint.eval(wl) * d65.eval(wl))
+        })
+        .collect();
+    Spectrum::Tabulated(crate::spectrum::Tabulated::new(samples).unwrap())
+}
+
+// wavelength intervals used for tabulating illuminants
+pub const SAMPLES: usize = 74;
+
+// luminance of a flat spectrum relative to d65's
+fn d65_luminance_ratio() -> f32 {
+    let d65 = Spectrum::from_bins(&D65);
+    let (mut y, mut y_d65) = (0.0, 0.0);
+    for i in 0..=SAMPLES {
+        let wl = MIN_WAVELENGTH + i as f32 * WAVELENGTH_RANGE / SAMPLES as f32;
+        // trapezoid rule
+        let w = if i == 0 || i == SAMPLES { 0.5 } else { 1.0 };
+        y += w * y_bar(wl);
+        y_d65 += w * y_bar(wl) * d65.eval(wl);
+    }
+    y / y_d65
+}
+
+pub const TABLE_RES: usize = 16;
+
+// coefficients by largest channel, its value & the other two channels relative to it, laid out
+// as little endian f32s indexed [channel][z][y][x], fitted by examples/fit_sigmoid_table.rs
+static TABLE: &[u8] = include_bytes!("sigmoid_table.bin");
+const _: () = assert!(TABLE.len() == 3 * TABLE_RES * TABLE_RES * TABLE_RES * 3 * 4);
+
+// brightnesses are spaced more densely near black & white
+pub fn z_node(i: usize) -> f32 {
+    let smoothstep = |x: f32| x * x * (3.0 - 2.0 * x);
+    smoothstep(smoothstep(i as f32 / (TABLE_RES - 1) as f32))
+}
+
+// trilinear, rgb in [0, 1] & not grey
+fn lookup(rgb: Vec3) -> [f32; 3] {
+    let res = TABLE_RES;
+    let channel = rgb.imax();
+    let z = rgb[channel];
+    let scale = (res - 1) as f32 / z;
+    let x = rgb[(channel + 1) % 3] * scale;
+    let y = rgb[(channel + 2) % 3] * scale;
+
+    let xi = (x as usize).min(res - 2);
+    let yi = (y as usize).min(res - 2);
+    let zi = (1..res - 1).take_while(|&i| z_node(i) <= z).count();
+    let (dx, dy) = (x - xi as f32, y - yi as f32);
+    let dz = (z - z_node(zi)) / (z_node(zi + 1) - z_node(zi));
+
+    let at = |z: usize, y: usize, x: usize| {
+        let i = (((channel * res + z) * res + y) * res + x) * 12;
+        Vec3::from_fn(|c, _| {
+            f32::from_le_bytes(TABLE[i + 4 * c..i + 4 * c + 4].try_into().unwrap())
+        })
+    };
+    let lerp = |a: Vec3, b: Vec3, t: f32| a * (1.0 - t) + b * t;
+    let c = lerp(
+        lerp(
+            lerp(at(zi, yi, xi), at(zi, yi, xi + 1), dx),
+            lerp(at(zi, yi + 1, xi), at(zi, yi + 1, xi + 1), dx),
+            dy,
+        ),
+        lerp(
+            lerp(at(zi + 1, yi, xi), at(zi + 1, yi, xi + 1), dx),
+            lerp(at(zi + 1, yi + 1, xi), at(zi + 1, yi + 1, xi + 1), dx),
+            dy,
+        ),
+        dz,
+    );
+    [c.x, c.y, c.z]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colour::{x_bar, xyz_to_rgb, z_bar};
+
+    // linear rgb of a reflectance under d65, a reflectance of 1 has a Y of 1
+    fn reflected_rgb(spectrum: &Spectrum) -> Vec3 {
+        let d65 = Spectrum::from_bins(&D65);
+        let (mut xyz, mut y) = (Vec3::zeros(), 0.0);
+        for i in 0..=SAMPLES {
+            let wl = MIN_WAVELENGTH + i as f32 * WAVELENGTH_RANGE / SAMPLES as f32;
+            // trapezoid rule
+            let w = if i == 0 || i == SAMPLES { 0.5 } else { 1.0 };
+            let weight = Vec3::new(x_bar(wl), y_bar(wl), z_bar(wl)) * d65.eval(wl) * w;
+            xyz += weight * spectrum.eval(wl);
+            y += weight.y;
+        }
+        xyz_to_rgb(xyz / y)
+    }
+
+    #[test]
+    fn sigmoid_upsampling() {
+        // round trips through the table
+        let colours = [
+            Vec3::new(0.8, 0.2, 0.1),
+            Vec3::new(0.1, 0.5, 0.2),
+            Vec3::new(0.2, 0.3, 0.7),
+            Vec3::new(0.9, 0.85, 0.3),
+            Vec3::new(0.05, 0.02, 0.03),
+        ];
+        for rgb in colours {
+            let spectrum = rgb_to_reflectance(rgb);
+            let error = (reflected_rgb(&spectrum) - rgb).abs().max();
+            assert!(error < 1e-2, "{rgb:?} {error}");
+        }
+
+        // greys are flat
+        let grey = rgb_to_reflectance(Vec3::repeat(0.5));
+        assert!(grey.is_constant() && (grey.eval(500.0) - 0.5).abs() < 1e-6);
+
+        // energy bounded however bright
+        for rgb in [
+            Vec3::repeat(2.0),
+            Vec3::new(5.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+        ] {
+            let (min, max) = rgb_to_reflectance(rgb).bounds();
+            assert!(min >= 0.0 && max < 1.0, "{rgb:?} {min} {max}");
+        }
+        assert_eq!(rgb_to_reflectance(Vec3::zeros()).bounds(), (0.0, 0.0));
+
+        // illuminants are d65 for white & scale with the rgb
+        let white = rgb_to_illuminant(Vec3::repeat(1.0));
+        let d65 = Spectrum::from_bins(&D65);
+        let ratio = white.eval(450.0) / d65.eval(450.0);
+        assert!((white.eval(650.0) / d65.eval(650.0) - ratio).abs() < 1e-3 * ratio);
+        let red = rgb_to_illuminant(Vec3::new(4.0, 0.0, 0.0));
+        assert!(red.eval(650.0) > 4.0 * red.eval(450.0));
+        assert!(red.eval(650.0) > rgb_to_illuminant(Vec3::new(2.0, 0.0, 0.0)).eval(650.0));
+    }
+}
@@ -0,0 +1,438 @@
+use crate::prelude::*;
+use std::str::FromStr;
+
+// random numbers in [0, 1) for one sample of one pixel, every value is a function of the seed,
+// pixel, sample index & dimension only so renders are reproducible regardless of threading
+pub trait Sampler {
+    fn start_pixel_sample(&mut self, pixel: [u32; 2], index: u32);
+    fn get_1d(&mut self) -> f32;
+    fn get_2d(&mut self) -> Vec2;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    // owen scrambled, the default
+    #[default]
+    Sobol,
+}
+
+impl SamplerKind {
+    // samples is the number of samples per pixel, used by the stratified sampler
+    pub fn build(self, seed: u64, samples: usize) -> Box<dyn Sampler + Send> {
+        let key = SampleKey::new(seed);
+        match self {
+            Self::Independent => Box::new(Independent { key }),
+            Self::Stratified => Box::new(Stratified::new(key, samples)),
+            Self::Halton => Box::new(Halton { key }),
+            Self::Sobol => Box::new(Sobol { key }),
+        }
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "independent" => Ok(Self::Independent),
+            "stratified" => Ok(Self::Stratified),
+            "halton" => Ok(Self::Halton),
+            "sobol" => Ok(Self::Sobol),
+            _ => Err(format!(
+                "unknown sampler \"{s}\", expected one of independent, stratified, halton, sobol"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SampleKey {
+    seed: u64,
+    pixel: [u32; 2],
+    index: u32,
+    dimension: u32,
+}
+
+impl SampleKey {
+    fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: [0, 0],
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn start(&mut self, pixel: [u32; 2], index: u32) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    // takes the next n dimensions, returning the first
+    fn next(&mut self, n: u32) -> u32 {
+        let dimension = self.dimension;
+        self.dimension += n;
+        dimension
+    }
+
+    // the same for every sample of a pixel
+    fn pixel_hash(&self, dimension: u32, salt: u64) -> u64 {
+        hash(&[
+            self.seed,
+            self.pixel[0] as u64,
+            self.pixel[1] as u64,
+            dimension as u64,
+            salt,
+        ])
+    }
+
+    fn sample_hash(&self, dimension: u32, salt: u64) -> u64 {
+        hash(&[
+            self.seed,
+            self.pixel[0] as u64,
+            self.pixel[1] as u64,
+            self.index as u64,
+            dimension as u64,
+            salt,
+        ])
+    }
+}
+
+fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    v
+}
+
+fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x9e3779b97f4a7c15, |h, &v| {
+        mix_bits(h.rotate_left(23) ^ v.wrapping_mul(0xbf58476d1ce4e5b9))
+    })
+}
+
+// the top 24 bits so the result is never rounded up to 1
+fn to_unit(bits: u64) -> f32 {
+    (bits >> 40) as f32 / (1u32 << 24) as f32
+}
+
+fn unit_from_u32(bits: u32) -> f32 {
+    (bits >> 8) as f32 / (1u32 << 24) as f32
+}
+
+// uncorrelated values, the baseline the others should beat
+#[derive(Debug, Clone)]
+pub struct Independent {
+    key: SampleKey,
+}
+
+impl Sampler for Independent {
+    fn start_pixel_sample(&mut self, pixel: [u32; 2], index: u32) {
+        self.key.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.key.next(1);
+        to_unit(self.key.sample_hash(dimension, 0))
+    }
+
+    fn get_2d(&mut self) -> Vec2 {
+        let dimension = self.key.next(2);
+        Vec2::new(
+            to_unit(self.key.sample_hash(dimension, 0)),
+            to_unit(self.key.sample_hash(dimension + 1, 0)),
+        )
+    }
+}
+
+// jittered strata of the pixel's sample count, visited in a different random order per dimension
+#[derive(Debug, Clone)]
+pub struct Stratified {
+    key: SampleKey,
+    samples: u32,
+    grid: [u32; 2],
+}
+
+impl Stratified {
+    fn new(key: SampleKey, samples: usize) -> Self {
+        let samples = samples.clamp(1, u32::MAX as usize) as u32;
+        let x = (samples as f32).sqrt().ceil() as u32;
+        Self {
+            key,
+            samples,
+            grid: [x, samples.div_ceil(x)],
+        }
+    }
+}
+
+impl Sampler for Stratified {
+    fn start_pixel_sample(&mut self, pixel: [u32; 2], index: u32) {
+        self.key.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.key.next(1);
+        let stratum = permutation_element(
+            self.key.index % self.samples,
+            self.samples,
+            self.key.pixel_hash(dimension, 0) as u32,
+        );
+        let jitter = to_unit(self.key.sample_hash(dimension, 1));
+        (stratum as f32 + jitter) / self.samples as f32
+    }
+
+    fn get_2d(&mut self) -> Vec2 {
+        let dimension = self.key.next(2);
+        let [x, y] = self.grid;
+        let cells = x * y;
+        let stratum = permutation_element(
+            self.key.index % cells,
+            cells,
+            self.key.pixel_hash(dimension, 0) as u32,
+        );
+        let jitter = Vec2::new(
+            to_unit(self.key.sample_hash(dimension, 1)),
+            to_unit(self.key.sample_hash(dimension + 1, 1)),
+        );
+        Vec2::new(
+            ((stratum % x) as f32 + jitter.x) / x as f32,
+            ((stratum / x) as f32 + jitter.y) / y as f32,
+        )
+    }
+}
+
+// kensler's hash based permutation of 0..n
+fn permutation_element(mut i: u32, n: u32, seed: u32) -> u32 {
+    let mut w = n.wrapping_sub(1);
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            return i.wrapping_add(seed) % n;
+        }
+    }
+}
+
+const HALTON_DIMENSIONS: usize = 256;
+const PRIMES: [u64; HALTON_DIMENSIONS] = primes();
+
+const fn primes<const N: usize>() -> [u64; N] {
+    let mut primes = [0; N];
+    let (mut count, mut candidate) = (0, 2);
+    while count < N {
+        let mut i = 0;
+        while i < count && candidate % primes[i] != 0 {
+            i += 1;
+        }
+        if i == count {
+            primes[count] = candidate;
+            count += 1;
+        }
+        candidate += 1;
+    }
+    primes
+}
+
+fn radical_inverse(base: u64, mut index: u64) -> f32 {
+    let inverse_base = 1.0 / base as f64;
+    let (mut digit_weight, mut result) = (inverse_base, 0.0);
+    while index > 0 {
+        result += (index % base) as f64 * digit_weight;
+        index /= base;
+        digit_weight *= inverse_base;
+    }
+    (result as f32).min(1.0 - f32::EPSILON / 2.0)
+}
+
+// the radical inverse in the nth prime base for dimension n, toroidally shifted per pixel, falls
+// back to independent values past the prime table
+#[derive(Debug, Clone)]
+pub struct Halton {
+    key: SampleKey,
+}
+
+impl Halton {
+    fn value(&self, dimension: u32) -> f32 {
+        let Some(&base) = PRIMES.get(dimension as usize) else {
+            return to_unit(self.key.sample_hash(dimension, 0));
+        };
+        let shift = to_unit(self.key.pixel_hash(dimension, 0));
+        let value = radical_inverse(base, self.key.index as u64) + shift;
+        (if value >= 1.0 { value - 1.0 } else { value }).min(1.0 - f32::EPSILON / 2.0)
+    }
+}
+
+impl Sampler for Halton {
+    fn start_pixel_sample(&mut self, pixel: [u32; 2], index: u32) {
+        self.key.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.key.next(1);
+        self.value(dimension)
+    }
+
+    fn get_2d(&mut self) -> Vec2 {
+        let dimension = self.key.next(2);
+        Vec2::new(self.value(dimension), self.value(dimension + 1))
+    }
+}
+
+// generator matrix of the second sobol dimension, the first is the bit reversal
+const SOBOL_MATRIX_1: [u32; 32] = {
+    let mut matrix = [0; 32];
+    matrix[0] = 1 << 31;
+    let mut i = 1;
+    while i < 32 {
+        matrix[i] = matrix[i - 1] ^ (matrix[i - 1] >> 1);
+        i += 1;
+    }
+    matrix
+};
+
+fn sobol(index: u32, dimension: u32) -> u32 {
+    if dimension == 0 {
+        return index.reverse_bits();
+    }
+    (0..32)
+        .filter(|bit| index & (1 << bit) != 0)
+        .fold(0, |v, bit| v ^ SOBOL_MATRIX_1[bit])
+}
+
+// burley 2020, practical hash-based owen scrambling
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+// the first two sobol dimensions owen scrambled per pixel, padded to more dimensions by
+// shuffling the sample index per dimension
+#[derive(Debug, Clone)]
+pub struct Sobol {
+    key: SampleKey,
+}
+
+impl Sobol {
+    fn shuffled_index(&self, dimension: u32) -> u32 {
+        nested_uniform_scramble(self.key.index, self.key.pixel_hash(dimension, 0) as u32)
+    }
+
+    fn scrambled(&self, index: u32, sobol_dimension: u32, dimension: u32) -> f32 {
+        let seed = self.key.pixel_hash(dimension, 1 + sobol_dimension as u64) as u32;
+        unit_from_u32(nested_uniform_scramble(sobol(index, sobol_dimension), seed))
+    }
+}
+
+impl Sampler for Sobol {
+    fn start_pixel_sample(&mut self, pixel: [u32; 2], index: u32) {
+        self.key.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.key.next(1);
+        self.scrambled(self.shuffled_index(dimension), 0, dimension)
+    }
+
+    fn get_2d(&mut self) -> Vec2 {
+        let dimension = self.key.next(2);
+        let index = self.shuffled_index(dimension);
+        Vec2::new(
+            self.scrambled(index, 0, dimension),
+            self.scrambled(index, 1, dimension),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samplers() {
+        let kinds = [
+            SamplerKind::Independent,
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ];
+        for kind in kinds {
+            let mut sampler = kind.build(7, 16);
+            let mut first = Vec::new();
+            let mut firsts_2d = Vec::new();
+            for index in 0..16 {
+                sampler.start_pixel_sample([3, 5], index);
+                first.push(sampler.get_1d());
+                firsts_2d.push(sampler.get_2d());
+                for _ in 0..300 {
+                    let v = sampler.get_1d();
+                    assert!((0.0..1.0).contains(&v), "{kind:?} {v}");
+                }
+            }
+
+            // reproducible, the same key gives the same value
+            let mut again = kind.build(7, 16);
+            again.start_pixel_sample([3, 5], 4);
+            assert_eq!(again.get_1d(), first[4]);
+            let mut reseeded = kind.build(8, 16);
+            reseeded.start_pixel_sample([3, 5], 4);
+            assert_ne!(reseeded.get_1d(), first[4]);
+
+            // one sample in each of the 16 strata
+            if kind != SamplerKind::Independent {
+                let mut strata = first.iter().map(|v| (v * 16.0) as u32).collect::<Vec<_>>();
+                strata.sort();
+                assert_eq!(strata, (0..16).collect::<Vec<_>>(), "{kind:?}");
+            }
+            if kind == SamplerKind::Stratified || kind == SamplerKind::Sobol {
+                let mut cells = firsts_2d
+                    .iter()
+                    .map(|v| (v.x * 4.0) as u32 + 4 * (v.y * 4.0) as u32)
+                    .collect::<Vec<_>>();
+                cells.sort();
+                assert_eq!(cells, (0..16).collect::<Vec<_>>(), "{kind:?}");
+            }
+        }
+
+        let n = 10;
+        let mut permutation = (0..n)
+            .map(|i| permutation_element(i, n, 1234))
+            .collect::<Vec<_>>();
+        permutation.sort();
+        assert_eq!(permutation, (0..n).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,204 @@
+use crate::{
+    animation::Animation,
+    prelude::*,
+    primitive::{Geometry, Primitive},
+    transform::{AnimatedTransform, Transform},
+};
+use bvh::{aabb::Aabound, tlas::Tlas};
+use derive_new::new;
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, new)]
+pub struct Instance {
+    pub geometry: usize,
+    // object to world
+    pub transform: Transform,
+    // overrides the materials of the geometry
+    pub mat: Option<usize>,
+    // from transform at time 0 to another transform at time 1
+    #[new(default)]
+    pub motion: Option<AnimatedTransform>,
+}
+
+impl Instance {
+    // None when the motion can't be interpolated, see AnimatedTransform::new
+    pub fn with_motion(mut self, end: Transform) -> Option<Self> {
+        self.motion = Some(AnimatedTransform::new(self.transform.clone(), end)?);
+        Some(self)
+    }
+
+    pub fn transform_at(&self, time: f32) -> Cow<'_, Transform> {
+        match &self.motion {
+            Some(motion) => Cow::Owned(motion.at(time)),
+            None => Cow::Borrowed(&self.transform),
+        }
+    }
+
+    pub fn to_world(&self, mut int: Intersection, time: f32) -> Intersection {
+        let transform = self.transform_at(time);
+        (int.pos, int.err) = transform.point_with_error(int.pos, int.err);
+        int.nor = transform.normal(int.nor).normalize();
+        int.dpdu = transform.vector(int.dpdu);
+        int.dpdv = transform.vector(int.dpdv);
+        if let Some(mat) = self.mat {
+            int.mat = mat;
+        }
+        int
+    }
+}
+
+impl bvh::tlas::Instance for Instance {
+    fn object_ray(&self, ray: &Ray) -> Ray {
+        self.transform_at(ray.time).inverse_ray(ray)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Scene {
+    pub materials: Vec<Mat>,
+    pub geometry: Vec<Geometry>,
+    pub instances: Vec<Instance>,
+    pub animations: Vec<Animation>,
+    tlas: Tlas,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_material(&mut self, mat: Mat) -> usize {
+        self.materials.push(mat);
+        self.materials.len() - 1
+    }
+
+    pub fn add_geometry(&mut self, geometry: impl Into<Geometry>) -> usize {
+        self.geometry.push(geometry.into());
+        self.geometry.len() - 1
+    }
+
+    pub fn add_instance(&mut self, instance: Instance) -> usize {
+        self.instances.push(instance);
+        self.instances.len() - 1
+    }
+
+    // adds the geometry with a single untransformed instance
+    pub fn add_geometry_instance(&mut self, geometry: impl Into<Geometry>) -> usize {
+        let geometry = self.add_geometry(geometry);
+        self.add_instance(Instance::new(geometry, Transform::identity(), None))
+    }
+
+    // needs to be called after all instances have been added
+    pub fn build_bvh(&mut self) {
+        let bounds = self
+            .instances
+            .iter()
+            .map(|i| {
+                let aabb = self.geometry[i.geometry].aabb();
+                match &i.motion {
+                    Some(motion) => motion.aabb(&aabb),
+                    None => i.transform.aabb(&aabb),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        self.tlas = Tlas::new(&bounds);
+    }
+
+    // poses animated instances at start moving to their pose at end over ray times 0 to 1,
+    // then rebuilds the bvh
+    pub fn set_frame_interval(&mut self, start: f32, end: f32) {
+        if self.animations.is_empty() {
+            return;
+        }
+        for animation in &self.animations {
+            let instance = &mut self.instances[animation.instance];
+            // poses with a zero scale keep the previous transform
+            let Some(from) = animation.track.at(start) else {
+                continue;
+            };
+            let to = animation.track.at(end).unwrap_or_else(|| from.clone());
+            // frames that flip the handedness are held still
+            instance.motion = (from != to)
+                .then(|| AnimatedTransform::new(from.clone(), to))
+                .flatten();
+            instance.transform = from;
+        }
+        self.build_bvh();
+    }
+
+    pub fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        self.tlas
+            .traverse(&self.instances, ray)
+            .into_iter()
+            .filter_map(|hit| {
+                let instance = &self.instances[hit.instance];
+                self.geometry[instance.geometry]
+                    .intersect(&hit.ray)
+                    .map(|int| instance.to_world(int, ray.time))
+            })
+            .min_by(|a, b| utility::float_cmp(a.t, b.t))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cornell_box::cornell_box;
+
+    #[test]
+    fn intersect_cornell_box() {
+        let mut scene = Scene::new();
+        cornell_box(&mut scene, 1.0);
+        scene.build_bvh();
+
+        let ray = Ray::new(Vec3::zeros(), Vec3::new(0.0, 0.0, 1.0));
+        let int = scene.intersect(&ray).unwrap();
+
+        assert!((int.t - 1.0).abs() < 1e-4);
+        assert!(int.out);
+        assert!((int.nor - Vec3::new(0.0, 0.0, -1.0)).magnitude() < 1e-4);
+
+        assert!(Scene::new().intersect(&ray).is_none());
+    }
+
+    #[test]
+    fn intersect_instances() {
+        let mut scene = Scene::new();
+        cornell_box(&mut scene, 1.0);
+        let geometry = scene.instances[0].geometry;
+        scene.add_instance(Instance::new(
+            geometry,
+            Transform::translate(Vec3::new(0.0, 0.0, 10.0))
+                * Transform::scale(Vec3::repeat(0.5)).unwrap(),
+            Some(1),
+        ));
+        scene.build_bvh();
+
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, 1.0));
+        let int = scene.intersect(&ray).unwrap();
+
+        assert!((int.t - 4.5).abs() < 1e-4);
+        assert!((int.pos - Vec3::new(0.0, 0.0, 9.5)).magnitude() < 1e-4);
+        assert_eq!(int.mat, 1);
+    }
+
+    #[test]
+    fn intersect_moving_instance() {
+        let mut scene = Scene::new();
+        let sphere = scene.add_geometry(Sphere::new(Vec3::zeros(), 1.0, 0));
+        scene.add_instance(
+            Instance::new(sphere, Transform::identity(), None)
+                .with_motion(Transform::translate(Vec3::new(10.0, 0.0, 0.0)))
+                .unwrap(),
+        );
+        scene.build_bvh();
+
+        let ray = |x, time| Ray::new_with_time(Vec3::new(x, -5.0, 0.0), Vec3::y(), time);
+        assert!(scene.intersect(&ray(0.0, 0.0)).is_some());
+        assert!(scene.intersect(&ray(0.0, 1.0)).is_none());
+        let int = scene.intersect(&ray(5.0, 0.5)).unwrap();
+        assert!((int.pos - Vec3::new(5.0, -1.0, 0.0)).magnitude() < 1e-4);
+        assert!(scene.intersect(&ray(10.0, 1.0)).is_some());
+    }
+}
@@ -0,0 +1,1459 @@
+use crate::{
+    animation::{Animation, Interpolation, Track, TransformTrack},
+    aperture::{Aperture, ApertureMask},
+    camera::{Equirectangular, Fisheye, MovingCamera, Orthographic, Perspective, Projection},
+    colour::srgb_to_linear,
+    cornell_box::cornell_box,
+    lens_system::{LensSystem, LensTable},
+    load_gltf::load_gltf,
+    load_obj::load_obj,
+    load_ply::load_ply,
+    material::{valid_reflectance, BINS},
+    mesh::{ImportOptions, NormalGeneration},
+    prelude::*,
+    rgb_spectrum::{rgb_to_illuminant, rgb_to_reflectance},
+    spectrum::Tabulated,
+    transform::Mat4,
+};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use toml::Spanned;
+
+#[derive(Debug)]
+pub struct SceneFileError {
+    pub path: PathBuf,
+    // line & column, both starting at 1
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Some((line, col)) => {
+                write!(f, "{}:{line}:{col}: {}", self.path.display(), self.message)
+            }
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for SceneFileError {}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDesc {
+    pub origin: [f32; 3],
+    pub look_at: [f32; 3],
+    #[serde(default = "default_up")]
+    pub up: [f32; 3],
+    #[serde(default = "default_hfov")]
+    pub hfov: f32,
+    #[serde(default = "default_focus_dist")]
+    pub focus_dist: f32,
+    // lens radius, or an f-number which needs scene units in metres
+    #[serde(default)]
+    pub aperture: f32,
+    pub f_stop: Option<f32>,
+    // a disk when not given
+    pub aperture_shape: Option<Spanned<ApertureDesc>>,
+    #[serde(default)]
+    pub projection: Projection,
+    // lens table in mm, replaces the projection, hfov & aperture, relative to the scene file
+    pub lens: Option<PathBuf>,
+    // where the camera is at shutter close
+    pub motion: Option<CameraMotionDesc>,
+    // replace origin, look_at, up & motion when rendering frames
+    #[serde(default)]
+    pub keyframes: Vec<CameraKeyframeDesc>,
+    #[serde(default)]
+    pub interpolation: Interpolation,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraKeyframeDesc {
+    // seconds
+    pub time: f32,
+    pub origin: [f32; 3],
+    pub look_at: [f32; 3],
+    #[serde(default = "default_up")]
+    pub up: [f32; 3],
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraMotionDesc {
+    pub origin: [f32; 3],
+    pub look_at: [f32; 3],
+    #[serde(default = "default_up")]
+    pub up: [f32; 3],
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ApertureDesc {
+    Disk,
+    // rotation in degrees
+    Polygon {
+        blades: u32,
+        #[serde(default)]
+        rotation: f32,
+    },
+    // relative paths are resolved against the scene file
+    Mask {
+        path: PathBuf,
+    },
+}
+
+// a view direction & an up that isn't parallel to it
+fn pose(origin: [f32; 3], look_at: [f32; 3], up: [f32; 3]) -> Result<(), String> {
+    let forward = Vec3::from(look_at) - Vec3::from(origin);
+    if forward.magnitude_squared() == 0.0 {
+        return Err("camera look_at can't be its origin".into());
+    }
+    if forward.cross(&Vec3::from(up)).magnitude_squared() == 0.0 {
+        return Err("camera up can't be parallel to the view direction".into());
+    }
+    Ok(())
+}
+
+fn default_up() -> [f32; 3] {
+    [0.0, 0.0, 1.0]
+}
+
+fn default_hfov() -> f32 {
+    70.0
+}
+
+fn default_focus_dist() -> f32 {
+    1.0
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+fn default_tint() -> [f32; 3] {
+    [1.0; 3]
+}
+
+fn default_crease_angle() -> f32 {
+    60.0
+}
+
+fn default_weld() -> bool {
+    true
+}
+
+impl CameraDesc {
+    // a pinhole perspective camera, the same as a scene file leaving the rest out
+    pub fn new(origin: [f32; 3], look_at: [f32; 3], up: [f32; 3], hfov: f32) -> Self {
+        Self {
+            origin,
+            look_at,
+            up,
+            hfov,
+            focus_dist: default_focus_dist(),
+            aperture: 0.0,
+            f_stop: None,
+            aperture_shape: None,
+            projection: Projection::Perspective,
+            lens: None,
+            motion: None,
+            keyframes: Vec::new(),
+            interpolation: Interpolation::Linear,
+        }
+    }
+
+    // posed from the keyframes at start & moving to their pose at end
+    pub fn at_interval(&self, start: f32, end: f32) -> Self {
+        let mut desc = self.clone();
+        let Some([origin, look_at, up]) = self.tracks() else {
+            return desc;
+        };
+        desc.origin = origin.at(start).into();
+        desc.look_at = look_at.at(start).into();
+        desc.up = up.at(start).into();
+        desc.motion = (end > start).then(|| CameraMotionDesc {
+            origin: origin.at(end).into(),
+            look_at: look_at.at(end).into(),
+            up: up.at(end).into(),
+        });
+        desc
+    }
+
+    // None without keyframes or when they're out of order
+    fn tracks(&self) -> Option<[Track<Vec3>; 3]> {
+        let track = |value: fn(&CameraKeyframeDesc) -> [f32; 3]| {
+            let keys = self.keyframes.iter().map(|k| (k.time, value(k).into()));
+            Track::new(keys.collect(), self.interpolation)
+        };
+        Some([
+            track(|k| k.origin)?,
+            track(|k| k.look_at)?,
+            track(|k| k.up)?,
+        ])
+    }
+
+    // the checks that would otherwise make a nan camera
+    fn validate(&self) -> Result<(), String> {
+        pose(self.origin, self.look_at, self.up)?;
+        if let Some(end) = &self.motion {
+            pose(end.origin, end.look_at, end.up)?;
+        }
+        for key in &self.keyframes {
+            pose(key.origin, key.look_at, key.up)?;
+        }
+        if self.focus_dist <= 0.0 {
+            return Err(format!(
+                "focus_dist must be positive, got {}",
+                self.focus_dist
+            ));
+        }
+        // replaced by the lens table
+        if self.lens.is_some() {
+            return Ok(());
+        }
+        let perspective = matches!(
+            self.projection,
+            Projection::Perspective | Projection::Orthographic
+        );
+        if perspective && !(self.hfov > 0.0 && self.hfov < 180.0) {
+            return Err(format!(
+                "hfov must be in the range (0, 180) degrees, got {}",
+                self.hfov
+            ));
+        }
+        if self.aperture < 0.0 {
+            return Err(format!("aperture can't be negative, got {}", self.aperture));
+        }
+        match self.f_stop {
+            Some(f_stop) if f_stop <= 0.0 => Err(format!("f_stop must be positive, got {f_stop}")),
+            _ => Ok(()),
+        }
+    }
+
+    // the aperture only applies to perspective cameras
+    pub fn build(&self, aspect_ratio: f32) -> Result<Box<dyn Camera>, String> {
+        let camera = self.build_static(aspect_ratio)?;
+        Ok(match &self.motion {
+            Some(end) => Box::new(MovingCamera::new(
+                camera,
+                [self.origin, self.look_at, self.up].map(Vec3::from),
+                [end.origin, end.look_at, end.up].map(Vec3::from),
+            )),
+            None => camera,
+        })
+    }
+
+    // at shutter open
+    fn build_static(&self, aspect_ratio: f32) -> Result<Box<dyn Camera>, String> {
+        let (origin, look_at, up) = (self.origin.into(), self.look_at.into(), self.up.into());
+        if let Some(path) = &self.lens {
+            let error = |e| format!("failed to load lens {}: {e}", path.display());
+            let table = LensTable::open(path).map_err(error)?;
+            let lens = LensSystem::new(origin, look_at, up, table, self.focus_dist, aspect_ratio)
+                .map_err(error)?;
+            return Ok(Box::new(lens));
+        }
+
+        Ok(match self.projection {
+            Projection::Perspective => {
+                let aperture = match self.f_stop {
+                    Some(f_stop) => Perspective::aperture_radius(self.hfov, f_stop),
+                    None => self.aperture,
+                };
+                let shape = match self.aperture_shape.as_ref().map(Spanned::get_ref) {
+                    None | Some(ApertureDesc::Disk) => Aperture::Disk,
+                    Some(&ApertureDesc::Polygon { blades, rotation }) => Aperture::Polygon {
+                        blades,
+                        rotation: rotation.to_radians(),
+                    },
+                    Some(ApertureDesc::Mask { path }) => {
+                        Aperture::Mask(Arc::new(ApertureMask::open(path).map_err(|e| {
+                            format!("failed to load aperture mask {}: {e}", path.display())
+                        })?))
+                    }
+                };
+                Box::new(
+                    Perspective::new(
+                        origin,
+                        look_at,
+                        up,
+                        self.hfov,
+                        self.focus_dist,
+                        aperture,
+                        aspect_ratio,
+                    )
+                    .with_aperture(shape),
+                )
+            }
+            // as wide as the perspective view at focus_dist
+            Projection::Orthographic => Box::new(Orthographic::new(
+                origin,
+                look_at,
+                up,
+                self.focus_dist * 2.0 * (0.5 * self.hfov.to_radians()).tan(),
+                aspect_ratio,
+            )),
+            Projection::Equirectangular => Box::new(Equirectangular::new(origin, look_at, up)),
+            Projection::Fisheye => {
+                Box::new(Fisheye::new(origin, look_at, up, self.hfov, aspect_ratio))
+            }
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    // defaults to the first camera from the gltf files
+    camera: Option<Spanned<CameraDesc>>,
+    cornell_box: Option<f32>,
+    #[serde(default)]
+    gltf: Vec<Spanned<String>>,
+    #[serde(default)]
+    materials: BTreeMap<String, Spanned<MatDesc>>,
+    #[serde(default)]
+    meshes: Vec<MeshDesc>,
+    #[serde(default)]
+    instances: Vec<InstanceDesc>,
+    #[serde(default)]
+    triangles: Vec<Spanned<TriangleDesc>>,
+    #[serde(default)]
+    lights: Vec<LightDesc>,
+    #[serde(default)]
+    spheres: Vec<Spanned<SphereDesc>>,
+    #[serde(default)]
+    disks: Vec<Spanned<DiskDesc>>,
+    #[serde(default)]
+    quads: Vec<Spanned<QuadDesc>>,
+    #[serde(default)]
+    cylinders: Vec<Spanned<CylinderDesc>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MatDesc {
+    SpectralPowerDistribution {
+        irradiance: Option<SpectrumDesc>,
+        illuminant: Option<Illuminant>,
+        #[serde(default = "default_scale")]
+        scale: f32,
+    },
+    SpectralReflectanceDistribution {
+        reflectance: SpectrumDesc,
+    },
+    SpectralRefract {
+        ior: SpectrumDesc,
+    },
+    SpectralMirror {
+        reflectance: SpectrumDesc,
+    },
+    Lambertian {
+        albedo: f32,
+    },
+    // diffuse with an srgb texture relative to the scene file, multiplied by a linear rgb tint
+    Textured {
+        texture: PathBuf,
+        #[serde(default = "default_tint")]
+        tint: [f32; 3],
+    },
+}
+
+// a constant, 16 bins evenly spaced from 380nm to 750nm, values at increasing wavelengths in nm,
+// a csv file of wavelength & value rows relative to the scene file, a black body temperature in
+// kelvin peaking at 1, cauchy's a & b with wavelengths in micrometres or a linear or srgb encoded
+// colour, upsampled to a reflectance or a d65 tinted illuminant
+#[derive(Debug, Deserialize)]
+#[serde(
+    untagged,
+    deny_unknown_fields,
+    expecting = "expected a spectrum as a number, 16 bins, { wavelengths, values }, { csv }, { blackbody }, { cauchy }, { rgb } or { srgb }"
+)]
+enum SpectrumDesc {
+    Constant(f32),
+    Bins([f32; BINS]),
+    Samples {
+        wavelengths: Vec<f32>,
+        values: Vec<f32>,
+    },
+    Csv {
+        csv: PathBuf,
+    },
+    Blackbody {
+        blackbody: f32,
+    },
+    Cauchy {
+        cauchy: [f32; 2],
+    },
+    Rgb {
+        rgb: [f32; 3],
+    },
+    Srgb {
+        srgb: [f32; 3],
+    },
+}
+
+// what a spectrum describes, decides how colours are upsampled
+#[derive(Debug, Clone, Copy)]
+enum SpectrumUse {
+    Reflectance,
+    Illuminant,
+    Ior,
+}
+
+impl SpectrumDesc {
+    fn build(self, dir: &Path, usage: SpectrumUse) -> Result<Spectrum, String> {
+        Ok(match self {
+            Self::Constant(value) => Spectrum::Constant(value),
+            Self::Bins(bins) => Spectrum::from_bins(&bins),
+            Self::Samples {
+                wavelengths,
+                values,
+            } => {
+                if wavelengths.len() != values.len() {
+                    return Err(format!(
+                        "{} wavelengths but {} values",
+                        wavelengths.len(),
+                        values.len()
+                    ));
+                }
+                let samples = wavelengths.into_iter().zip(values).collect();
+                Spectrum::Tabulated(
+                    Tabulated::new(samples)
+                        .ok_or("spectrum wavelengths must increase & not be empty")?,
+                )
+            }
+            Self::Csv { csv } => {
+                let path = dir.join(csv);
+                Spectrum::open_csv(&path)
+                    .map_err(|e| format!("failed to load spectrum {}: {e}", path.display()))?
+            }
+            Self::Blackbody { blackbody } => {
+                if blackbody <= 0.0 {
+                    return Err("black body temperatures must be positive".into());
+                }
+                Spectrum::Blackbody(blackbody)
+            }
+            Self::Cauchy { cauchy: [a, b] } => Spectrum::Cauchy { a, b },
+            Self::Rgb { rgb } => colour(Vec3::from(rgb), usage)?,
+            Self::Srgb { srgb } => colour(Vec3::from(srgb).map(srgb_to_linear), usage)?,
+        })
+    }
+}
+
+fn colour(rgb: Vec3, usage: SpectrumUse) -> Result<Spectrum, String> {
+    if rgb.iter().any(|v| *v < 0.0) {
+        return Err("colour channels can't be negative".into());
+    }
+    match usage {
+        SpectrumUse::Reflectance => Ok(rgb_to_reflectance(rgb)),
+        SpectrumUse::Illuminant => Ok(rgb_to_illuminant(rgb)),
+        SpectrumUse::Ior => Err("an ior can't be given as a colour".into()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Illuminant {
+    D65,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(untagged)]
+enum Scale {
+    Uniform(f32),
+    Axes([f32; 3]),
+}
+
+impl Default for Scale {
+    fn default() -> Self {
+        Self::Uniform(1.0)
+    }
+}
+
+// placed once, a name allows more copies through instances
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MeshDesc {
+    name: Option<Spanned<String>>,
+    // obj or ply
+    path: Spanned<String>,
+    // overrides the obj's mtl materials or the ply's vertex colours
+    material: Option<Spanned<String>>,
+    // degrees, used when the file has no normals, 0 gives flat shading
+    #[serde(default = "default_crease_angle")]
+    crease_angle: f32,
+    #[serde(default = "default_weld")]
+    weld: bool,
+    #[serde(default)]
+    scale: Scale,
+    // degrees around x, y then z
+    #[serde(default)]
+    rotate: [f32; 3],
+    #[serde(default)]
+    offset: [f32; 3],
+    // row major affine matrix, can't be combined with scale, rotate or offset
+    matrix: Option<[[f32; 4]; 3]>,
+    motion: Option<MotionDesc>,
+    // replace the transform & motion when rendering frames
+    #[serde(default)]
+    keyframes: Vec<KeyframeDesc>,
+    #[serde(default)]
+    interpolation: Interpolation,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InstanceDesc {
+    mesh: Spanned<String>,
+    material: Option<Spanned<String>>,
+    #[serde(default)]
+    scale: Scale,
+    #[serde(default)]
+    rotate: [f32; 3],
+    #[serde(default)]
+    offset: [f32; 3],
+    matrix: Option<[[f32; 4]; 3]>,
+    motion: Option<MotionDesc>,
+    // replace the transform & motion when rendering frames
+    #[serde(default)]
+    keyframes: Vec<KeyframeDesc>,
+    #[serde(default)]
+    interpolation: Interpolation,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyframeDesc {
+    // seconds
+    time: f32,
+    #[serde(default)]
+    scale: Scale,
+    #[serde(default)]
+    rotate: [f32; 3],
+    #[serde(default)]
+    offset: [f32; 3],
+}
+
+// the transform at shutter close, replacing the one at shutter open
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MotionDesc {
+    #[serde(default)]
+    scale: Scale,
+    #[serde(default)]
+    rotate: [f32; 3],
+    #[serde(default)]
+    offset: [f32; 3],
+    matrix: Option<[[f32; 4]; 3]>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TriangleDesc {
+    vertices: [[f32; 3]; 3],
+    normals: Option<[[f32; 3]; 3]>,
+    uvs: Option<[[f32; 2]; 3]>,
+    material: Spanned<String>,
+}
+
+// emissive triangle or quad
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LightDesc {
+    vertices: Spanned<Vec<[f32; 3]>>,
+    irradiance: Option<SpectrumDesc>,
+    illuminant: Option<Illuminant>,
+    #[serde(default = "default_scale")]
+    scale: f32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SphereDesc {
+    centre: [f32; 3],
+    radius: f32,
+    material: Spanned<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DiskDesc {
+    centre: [f32; 3],
+    normal: [f32; 3],
+    radius: f32,
+    material: Spanned<String>,
+}
+
+// parallelogram spanned by u & v from corner
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QuadDesc {
+    corner: [f32; 3],
+    u: [f32; 3],
+    v: [f32; 3],
+    material: Spanned<String>,
+}
+
+// open tube from base to base + axis
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CylinderDesc {
+    base: [f32; 3],
+    axis: [f32; 3],
+    radius: f32,
+    material: Spanned<String>,
+}
+
+// gltf & glb files are loaded directly, anything else as a toml scene file
+pub fn load_scene(path: impl AsRef<Path>) -> Result<(Scene, CameraDesc), SceneFileError> {
+    let path = path.as_ref();
+    let ext = path.extension().and_then(|e| e.to_str());
+    let error = |message: String| SceneFileError {
+        path: path.to_path_buf(),
+        location: None,
+        message,
+    };
+
+    if matches!(ext.map(str::to_lowercase).as_deref(), Some("gltf" | "glb")) {
+        let mut scene = Scene::new();
+        let camera = load_gltf(
+            &path.to_string_lossy(),
+            &mut scene,
+            &ImportOptions::default(),
+        )
+        .map_err(|e| error(e.to_string()))?
+        .into_iter()
+        .next();
+        let camera = camera.ok_or_else(|| error("scene contains no camera".into()))?;
+        if scene.instances.is_empty() {
+            return Err(error("scene contains no geometry".into()));
+        }
+        scene.build_bvh();
+        return Ok((scene, camera));
+    }
+
+    let src = std::fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+    parse_scene(&src, path)
+}
+
+// relative mesh paths are resolved against the directory containing path
+pub fn parse_scene(src: &str, path: &Path) -> Result<(Scene, CameraDesc), SceneFileError> {
+    let error = |span: Option<Range<usize>>, message: String| SceneFileError {
+        path: path.to_path_buf(),
+        location: span.map(|s| line_col(src, s.start)),
+        message,
+    };
+
+    let desc: SceneDesc = toml::from_str(src).map_err(|e| error(e.span(), e.message().into()))?;
+
+    let mut scene = Scene::new();
+
+    if let Some(scale) = desc.cornell_box {
+        cornell_box(&mut scene, scale);
+    }
+
+    let mut cameras = Vec::new();
+    for gltf in &desc.gltf {
+        let gltf_path = path.parent().unwrap_or(Path::new("")).join(gltf.get_ref());
+        let loaded = load_gltf(
+            &gltf_path.to_string_lossy(),
+            &mut scene,
+            &ImportOptions::default(),
+        )
+        .map_err(|e| {
+            error(
+                Some(gltf.span()),
+                format!("failed to load {}: {e}", gltf_path.display()),
+            )
+        })?;
+        cameras.extend(loaded);
+    }
+    // cameras from gltf files have no location
+    let camera_span = desc.camera.as_ref().map(Spanned::span);
+    let camera = desc.camera.map(Spanned::into_inner);
+    let Some(mut camera) = camera.or_else(|| cameras.into_iter().next()) else {
+        return Err(error(None, "scene contains no camera".into()));
+    };
+    if !camera.keyframes.is_empty() && camera.tracks().is_none() {
+        return Err(error(
+            camera_span,
+            "camera keyframe times must increase".into(),
+        ));
+    }
+    camera
+        .validate()
+        .map_err(|e| error(camera_span.clone(), e))?;
+    if let Some(shape) = &mut camera.aperture_shape {
+        let span = shape.span();
+        match shape.get_mut() {
+            ApertureDesc::Polygon { blades, .. } if *blades < 3 => {
+                return Err(error(
+                    Some(span),
+                    format!("aperture polygons need at least 3 blades, got {blades}"),
+                ));
+            }
+            ApertureDesc::Mask { path: mask } => {
+                *mask = path.parent().unwrap_or(Path::new("")).join(&mask);
+            }
+            _ => (),
+        }
+    }
+    if let Some(lens) = &mut camera.lens {
+        *lens = path.parent().unwrap_or(Path::new("")).join(&lens);
+    }
+
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut names = BTreeMap::new();
+    for (name, mat) in desc.materials {
+        let span = mat.span();
+        let mat = build_mat(mat.into_inner(), dir).map_err(|e| error(Some(span), e))?;
+        names.insert(name, scene.materials.len());
+        scene.materials.push(mat);
+    }
+
+    let lookup = |name: &Spanned<String>| {
+        names.get(name.get_ref()).copied().ok_or_else(|| {
+            error(
+                Some(name.span()),
+                format!("unknown material \"{}\"", name.get_ref()),
+            )
+        })
+    };
+
+    let mut mesh_names = BTreeMap::new();
+    for mesh in &desc.meshes {
+        let mat = mesh.material.as_ref().map(lookup).transpose()?;
+        let mesh_path = path
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(mesh.path.get_ref());
+        let transform = transform(mesh.scale, mesh.rotate, mesh.offset, mesh.matrix)
+            .map_err(|e| error(Some(mesh.path.span()), e))?;
+
+        let options = ImportOptions {
+            normals: if mesh.crease_angle > 0.0 {
+                NormalGeneration::Smooth {
+                    crease_angle: mesh.crease_angle,
+                }
+            } else {
+                NormalGeneration::Flat
+            },
+            weld: mesh.weld,
+        };
+        let ply = mesh_path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("ply"));
+        let mesh_file = mesh_path.to_string_lossy();
+        let loaded = match mat {
+            Some(mat) if ply => load_ply(&mesh_file, mat, &options),
+            Some(mat) => load_obj(&mesh_file, mat, None, &options),
+            None if ply => {
+                // added after loading so vertex colours can be used when present
+                let loaded = load_ply(&mesh_file, scene.materials.len(), &options);
+                scene.add_material(Mat::VertexColour(VertexColour::new(Vec3::repeat(0.8))));
+                loaded
+            }
+            None => {
+                // for faces without a mtl material
+                let fallback = scene.add_material(Mat::Lambertian(Lambertian::new(0.8)));
+                load_obj(&mesh_file, fallback, Some(&mut scene.materials), &options)
+            }
+        };
+        let loaded = loaded.map_err(|e| {
+            error(
+                Some(mesh.path.span()),
+                format!("failed to load {}: {e}", mesh_path.display()),
+            )
+        })?;
+        let idx = scene.add_geometry(loaded);
+        let motion = motion(&mesh.motion).map_err(|e| error(Some(mesh.path.span()), e))?;
+        let transformed = mesh.matrix.is_some() || has_parts(mesh.scale, mesh.rotate, mesh.offset);
+        let track = keyframes(
+            &mesh.keyframes,
+            mesh.interpolation,
+            &mesh.motion,
+            transformed,
+        )
+        .map_err(|e| error(Some(mesh.path.span()), e))?;
+        let instance = animated(Instance::new(idx, transform, None), motion)
+            .map_err(|e| error(Some(mesh.path.span()), e))?;
+        let instance = scene.add_instance(instance);
+        if let Some(track) = track {
+            scene.animations.push(Animation { instance, track });
+        }
+
+        if let Some(name) = &mesh.name {
+            if mesh_names.insert(name.get_ref().clone(), idx).is_some() {
+                return Err(error(
+                    Some(name.span()),
+                    format!("duplicate mesh name \"{}\"", name.get_ref()),
+                ));
+            }
+        }
+    }
+
+    for instance in &desc.instances {
+        let Some(&mesh) = mesh_names.get(instance.mesh.get_ref()) else {
+            return Err(error(
+                Some(instance.mesh.span()),
+                format!("unknown mesh \"{}\"", instance.mesh.get_ref()),
+            ));
+        };
+        let mat = instance.material.as_ref().map(lookup).transpose()?;
+        let transform = transform(
+            instance.scale,
+            instance.rotate,
+            instance.offset,
+            instance.matrix,
+        )
+        .map_err(|e| error(Some(instance.mesh.span()), e))?;
+        let motion = motion(&instance.motion).map_err(|e| error(Some(instance.mesh.span()), e))?;
+        let transformed = instance.matrix.is_some()
+            || has_parts(instance.scale, instance.rotate, instance.offset);
+        let track = keyframes(
+            &instance.keyframes,
+            instance.interpolation,
+            &instance.motion,
+            transformed,
+        )
+        .map_err(|e| error(Some(instance.mesh.span()), e))?;
+
+        let instance = animated(Instance::new(mesh, transform, mat), motion)
+            .map_err(|e| error(Some(instance.mesh.span()), e))?;
+        let instance = scene.add_instance(instance);
+        if let Some(track) = track {
+            scene.animations.push(Animation { instance, track });
+        }
+    }
+
+    // inline triangles and lights share a single mesh
+    let (mut vertices, mut normals, mut uvs, mut triangles) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    let mut add_polygon = |verts: &[Vec3], norms: &[Vec3], tex: Option<&[Vec2]>, mat: usize| {
+        let (vo, no, uo) = (vertices.len(), normals.len(), uvs.len());
+
+        vertices.extend_from_slice(verts);
+        normals.extend_from_slice(&norms[..verts.len()]);
+        if let Some(tex) = tex {
+            uvs.extend_from_slice(tex);
+        }
+
+        // triangle or quad, split as a fan
+        for i in 1..verts.len() - 1 {
+            let triangle = Triangle::new([vo, vo + i, vo + i + 1], [no, no + i, no + i + 1], mat);
+            triangles.push(match tex {
+                Some(_) => triangle.with_uv([uo, uo + i, uo + i + 1]),
+                None => triangle,
+            });
+        }
+    };
+
+    for tri in &desc.triangles {
+        let (span, tri) = (tri.span(), tri.get_ref());
+        let mat = lookup(&tri.material)?;
+        let vertices = tri.vertices.map(Vec3::from);
+        let normals = match tri.normals {
+            Some(normals) => {
+                let normals = normals.map(|n| Vec3::from(n).try_normalize(0.0));
+                match normals {
+                    [Some(n0), Some(n1), Some(n2)] => [n0, n1, n2],
+                    _ => return Err(error(Some(span), "normals can't be zero".into())),
+                }
+            }
+            None => [flat_normal(&vertices).map_err(|e| error(Some(span), e))?; 3],
+        };
+        let uvs = tri.uvs.map(|uvs| uvs.map(Vec2::from));
+        add_polygon(&vertices, &normals, uvs.as_ref().map(|v| &v[..]), mat);
+    }
+
+    for light in desc.lights {
+        let span = light.vertices.span();
+        let vertices = light
+            .vertices
+            .into_inner()
+            .into_iter()
+            .map(Vec3::from)
+            .collect::<Vec<_>>();
+        if vertices.len() != 3 && vertices.len() != 4 {
+            return Err(error(
+                Some(span),
+                format!(
+                    "lights need 3 (triangle) or 4 (quad) vertices, got {}",
+                    vertices.len()
+                ),
+            ));
+        }
+        let spd = emission(light.irradiance, light.illuminant, light.scale, dir)
+            .map_err(|e| error(Some(span.clone()), e))?;
+
+        let normal = flat_normal(&vertices).map_err(|e| error(Some(span), e))?;
+        let mat = scene.add_material(Mat::SpectralPowerDistribution(spd));
+        add_polygon(&vertices, &[normal; 4], None, mat);
+    }
+
+    if !triangles.is_empty() {
+        scene.add_geometry_instance(Mesh::new_with_uvs(vertices, normals, uvs, triangles));
+    }
+
+    for sphere in &desc.spheres {
+        let (span, sphere) = (sphere.span(), sphere.get_ref());
+        let mat = lookup(&sphere.material)?;
+        positive_radius(sphere.radius).map_err(|e| error(Some(span), e))?;
+        scene.add_geometry_instance(Sphere::new(sphere.centre.into(), sphere.radius, mat));
+    }
+
+    for disk in &desc.disks {
+        let (span, disk) = (disk.span(), disk.get_ref());
+        let mat = lookup(&disk.material)?;
+        positive_radius(disk.radius)
+            .and_then(|_| non_zero(disk.normal.into(), "disk normal"))
+            .map_err(|e| error(Some(span), e))?;
+        scene.add_geometry_instance(Disk::new(
+            disk.centre.into(),
+            disk.normal.into(),
+            disk.radius,
+            mat,
+        ));
+    }
+
+    for quad in &desc.quads {
+        let (span, quad) = (quad.span(), quad.get_ref());
+        let mat = lookup(&quad.material)?;
+        if Vec3::from(quad.u)
+            .cross(&Vec3::from(quad.v))
+            .magnitude_squared()
+            == 0.0
+        {
+            return Err(error(
+                Some(span),
+                "quad u & v must be non-zero & not parallel".into(),
+            ));
+        }
+        scene.add_geometry_instance(Quad::new(
+            quad.corner.into(),
+            quad.u.into(),
+            quad.v.into(),
+            mat,
+        ));
+    }
+
+    for cylinder in &desc.cylinders {
+        let (span, cylinder) = (cylinder.span(), cylinder.get_ref());
+        let mat = lookup(&cylinder.material)?;
+        positive_radius(cylinder.radius)
+            .and_then(|_| non_zero(cylinder.axis.into(), "cylinder axis"))
+            .map_err(|e| error(Some(span), e))?;
+        scene.add_geometry_instance(Cylinder::new(
+            cylinder.base.into(),
+            cylinder.axis.into(),
+            cylinder.radius,
+            mat,
+        ));
+    }
+
+    if scene.instances.is_empty() {
+        return Err(error(None, "scene contains no geometry".into()));
+    }
+
+    scene.build_bvh();
+
+    Ok((scene, camera))
+}
+
+fn build_mat(desc: MatDesc, dir: &Path) -> Result<Mat, String> {
+    Ok(match desc {
+        MatDesc::SpectralPowerDistribution {
+            irradiance,
+            illuminant,
+            scale,
+        } => Mat::SpectralPowerDistribution(emission(irradiance, illuminant, scale, dir)?),
+        MatDesc::SpectralReflectanceDistribution { reflectance } => {
+            let reflectance = reflectance.build(dir, SpectrumUse::Reflectance)?;
+            if !valid_reflectance(&reflectance) {
+                return Err("reflectance values must be in the range [0, 1)".into());
+            }
+            Mat::SpectralReflectanceDistribution(SpectralReflectanceDistribution::new(reflectance))
+        }
+        MatDesc::SpectralRefract { ior } => {
+            let ior = ior.build(dir, SpectrumUse::Ior)?;
+            if ior.bounds().0 <= 0.0 {
+                return Err("ior values must be positive".into());
+            }
+            Mat::SpectralRefract(SpectralRefract::new(ior))
+        }
+        MatDesc::SpectralMirror { reflectance } => {
+            let reflectance = reflectance.build(dir, SpectrumUse::Reflectance)?;
+            if !valid_reflectance(&reflectance) {
+                return Err("reflectance values must be in the range [0, 1)".into());
+            }
+            Mat::SpectralMirror(SpectralMirror::new(reflectance))
+        }
+        MatDesc::Lambertian { albedo } => {
+            if !valid_reflectance(&Spectrum::Constant(albedo)) {
+                return Err("albedo must be in the range [0, 1)".into());
+            }
+            Mat::Lambertian(Lambertian::new(albedo))
+        }
+        MatDesc::Textured { texture, tint } => {
+            let path = dir.join(texture);
+            let texture = Texture::open(&path)
+                .map_err(|e| format!("failed to load texture {}: {e}", path.display()))?;
+            Mat::Textured(Textured::new(Arc::new(texture), Vec3::from(tint)))
+        }
+    })
+}
+
+fn emission(
+    irradiance: Option<SpectrumDesc>,
+    illuminant: Option<Illuminant>,
+    scale: f32,
+    dir: &Path,
+) -> Result<SpectralPowerDistribution, String> {
+    match (irradiance, illuminant) {
+        (Some(irradiance), None) => Ok(SpectralPowerDistribution::new_with_scale(
+            irradiance.build(dir, SpectrumUse::Illuminant)?,
+            scale,
+        )),
+        (None, Some(Illuminant::D65)) => Ok(SpectralPowerDistribution::d65_illuminant(scale)),
+        _ => Err("exactly one of irradiance or illuminant must be given".into()),
+    }
+}
+
+// also rejects nan
+fn positive_radius(radius: f32) -> Result<(), String> {
+    if radius > 0.0 {
+        Ok(())
+    } else {
+        Err(format!("radius must be positive, got {radius}"))
+    }
+}
+
+fn non_zero(v: Vec3, name: &str) -> Result<(), String> {
+    if v.magnitude_squared() == 0.0 {
+        return Err(format!("{name} can't be zero"));
+    }
+    Ok(())
+}
+
+fn flat_normal(vertices: &[Vec3]) -> Result<Vec3, String> {
+    (vertices[1] - vertices[0])
+        .cross(&(vertices[2] - vertices[0]))
+        .try_normalize(0.0)
+        .ok_or_else(|| "vertices can't be collinear".into())
+}
+
+// whether any of scale, rotate or offset differ from the identity
+fn has_parts(scale: Scale, rotate: [f32; 3], offset: [f32; 3]) -> bool {
+    !matches!(scale, Scale::Uniform(s) if s == 1.0) || rotate != [0.0; 3] || offset != [0.0; 3]
+}
+
+fn transform(
+    scale: Scale,
+    rotate: [f32; 3],
+    offset: [f32; 3],
+    matrix: Option<[[f32; 4]; 3]>,
+) -> Result<Transform, String> {
+    if let Some(rows) = matrix {
+        if has_parts(scale, rotate, offset) {
+            return Err("matrix can't be combined with scale, rotate or offset".into());
+        }
+        let mut m = Mat4::identity();
+        for (i, row) in rows.iter().enumerate() {
+            for (j, v) in row.iter().enumerate() {
+                m[(i, j)] = *v;
+            }
+        }
+        return Transform::new(m).ok_or_else(|| "matrix is not invertible".into());
+    }
+
+    let scale = match scale {
+        Scale::Uniform(s) => Vec3::repeat(s),
+        Scale::Axes(s) => s.into(),
+    };
+    Transform::from_parts(scale, rotate.into(), offset.into())
+        .ok_or("scale must be non-zero".into())
+}
+
+fn motion(desc: &Option<MotionDesc>) -> Result<Option<Transform>, String> {
+    desc.as_ref()
+        .map(|m| transform(m.scale, m.rotate, m.offset, m.matrix))
+        .transpose()
+}
+
+// transformed says whether a static transform was given, which keyframes would replace
+fn keyframes(
+    keys: &[KeyframeDesc],
+    interpolation: Interpolation,
+    motion: &Option<MotionDesc>,
+    transformed: bool,
+) -> Result<Option<TransformTrack>, String> {
+    if keys.is_empty() {
+        return Ok(None);
+    }
+    if motion.is_some() {
+        return Err("keyframes can't be combined with motion".into());
+    }
+    if transformed {
+        return Err("keyframes can't be combined with scale, rotate, offset or matrix".into());
+    }
+
+    let track = |value: fn(&KeyframeDesc) -> Vec3| {
+        let keys = keys.iter().map(|k| (k.time, value(k))).collect();
+        Track::new(keys, interpolation).ok_or("keyframe times must increase")
+    };
+    Ok(Some(TransformTrack {
+        scale: track(|k| match k.scale {
+            Scale::Uniform(s) => Vec3::repeat(s),
+            Scale::Axes(s) => s.into(),
+        })?,
+        rotate: track(|k| k.rotate.into())?,
+        offset: track(|k| k.offset.into())?,
+    }))
+}
+
+fn animated(instance: Instance, motion: Option<Transform>) -> Result<Instance, String> {
+    match motion {
+        Some(end) => instance
+            .with_motion(end)
+            .ok_or_else(|| "motion can't change whether the object is mirrored".into()),
+        None => Ok(instance),
+    }
+}
+
+fn line_col(src: &str, offset: usize) -> (usize, usize) {
+    let before = &src[..offset.min(src.len())];
+    let line = before.matches('\n').count() + 1;
+    let col = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    (line, col)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    const SCENE: &str = r#"
+cornell_box = 1.0
+
+[camera]
+origin = [0.0, -2.5, 0.0]
+look_at = [0.0, 0.0, 0.0]
+
+[materials.glass]
+type = "spectral_refract"
+ior = [1.5, 1.5, 1.5, 1.5, 1.5, 1.5, 1.5, 1.5, 1.5, 1.5, 1.5, 1.5, 1.5, 1.5, 1.5, 1.5]
+
+[[triangles]]
+vertices = [[0.0, 0.5, 0.0], [-0.5, -0.5, 0.0], [0.5, -0.5, 0.0]]
+uvs = [[0.5, 1.0], [0.0, 0.0], [1.0, 0.0]]
+material = "glass"
+
+[[lights]]
+vertices = [[-0.2, -0.2, 0.99], [0.2, -0.2, 0.99], [0.2, 0.2, 0.99], [-0.2, 0.2, 0.99]]
+illuminant = "d65"
+scale = 1e-3
+
+[[spheres]]
+centre = [0.5, 0.5, -0.7]
+radius = 0.3
+material = "glass"
+"#;
+
+    #[test]
+    fn parse_scene_file() {
+        let (scene, camera) = parse_scene(SCENE, Path::new("test.toml")).unwrap();
+
+        // cornell box (3) + glass + light
+        assert_eq!(scene.materials.len(), 5);
+        // cornell box, the triangle & quad sharing a mesh, then the sphere
+        assert_eq!(scene.instances.len(), 3);
+        let Geometry::Mesh(mesh) = &scene.geometry[1] else {
+            panic!("expected a mesh");
+        };
+        assert_eq!(mesh.triangles.len(), 3);
+        assert_eq!(mesh.uvs.len(), 3);
+        assert!(matches!(scene.geometry[2], Geometry::Sphere(_)));
+        assert_eq!(camera.up, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn scene_file_error_location() {
+        let src = SCENE.replace("material = \"glass\"", "material = \"metal\"");
+        let err = parse_scene(&src, Path::new("test.toml")).unwrap_err();
+        assert_eq!(err.location, Some((15, 12)));
+        assert_eq!(
+            err.to_string(),
+            "test.toml:15:12: unknown material \"metal\""
+        );
+
+        let src = SCENE.replace("1.5, 1.5]", "1.5]");
+        let err = parse_scene(&src, Path::new("test.toml")).unwrap_err();
+        assert_eq!(err.location.unwrap().0, 8);
+
+        let src = SCENE.replace(
+            "look_at = [0.0, 0.0, 0.0]\n",
+            "look_at = [0.0, 0.0, 0.0]\naperture_shape = { type = \"polygon\", blades = 2 }\n",
+        );
+        let err = parse_scene(&src, Path::new("test.toml")).unwrap_err();
+        assert_eq!(err.location, Some((7, 18)));
+
+        // albedos share the reflectance range
+        let ior = "ior = [".to_string() + &["1.5"; 16].join(", ") + "]";
+        let src = SCENE
+            .replace(&ior, "albedo = 1.0")
+            .replace("\"spectral_refract\"", "\"lambertian\"");
+        let err = parse_scene(&src, Path::new("test.toml")).unwrap_err();
+        assert_eq!(err.message, "albedo must be in the range [0, 1)");
+    }
+
+    #[test]
+    fn invalid_camera_and_normals() {
+        let cameras = [
+            (
+                "up = [0.0, 2.0, 0.0]",
+                "camera up can't be parallel to the view direction",
+            ),
+            (
+                "hfov = 180.0",
+                "hfov must be in the range (0, 180) degrees, got 180",
+            ),
+            ("focus_dist = 0.0", "focus_dist must be positive, got 0"),
+            ("aperture = -0.1", "aperture can't be negative, got -0.1"),
+        ];
+        for (field, message) in cameras {
+            let src = SCENE.replace(
+                "look_at = [0.0, 0.0, 0.0]\n",
+                &format!("look_at = [0.0, 0.0, 0.0]\n{field}\n"),
+            );
+            let err = parse_scene(&src, Path::new("test.toml")).unwrap_err();
+            assert_eq!(err.message, message);
+            assert_eq!(err.location.unwrap().0, 4);
+        }
+
+        let normals = "normals = [[0.0, 0.0, 1.0], [0.0, 0.0, 0.0], [0.0, 0.0, 1.0]]";
+        let src = SCENE.replace("uvs = ", &format!("{normals}\nuvs = "));
+        let err = parse_scene(&src, Path::new("test.toml")).unwrap_err();
+        assert_eq!(err.message, "normals can't be zero");
+        assert_eq!(err.location.unwrap().0, 12);
+
+        let src = SCENE.replace("[0.5, -0.5, 0.0]]", "[0.5, 1.5, 0.0]]");
+        let err = parse_scene(&src, Path::new("test.toml")).unwrap_err();
+        assert_eq!(err.message, "vertices can't be collinear");
+        assert_eq!(err.location.unwrap().0, 12);
+    }
+
+    #[test]
+    fn invalid_shapes() {
+        let src = SCENE.replace("radius = 0.3", "radius = 0.0");
+        let err = parse_scene(&src, Path::new("test.toml")).unwrap_err();
+        assert_eq!(err.message, "radius must be positive, got 0");
+        assert_eq!(err.location.unwrap().0, 22);
+
+        let shapes = [
+            (
+                "[[disks]]\ncentre = [0.0, 0.0, 0.0]\nnormal = [0.0, 0.0, 1.0]\nradius = -1.0",
+                "radius must be positive, got -1",
+            ),
+            (
+                "[[disks]]\ncentre = [0.0, 0.0, 0.0]\nnormal = [0.0, 0.0, 0.0]\nradius = 1.0",
+                "disk normal can't be zero",
+            ),
+            (
+                "[[quads]]\ncorner = [0.0, 0.0, 0.0]\nu = [1.0, 0.0, 0.0]\nv = [2.0, 0.0, 0.0]",
+                "quad u & v must be non-zero & not parallel",
+            ),
+            (
+                "[[quads]]\ncorner = [0.0, 0.0, 0.0]\nu = [1.0, 0.0, 0.0]\nv = [0.0, 0.0, 0.0]",
+                "quad u & v must be non-zero & not parallel",
+            ),
+            (
+                "[[cylinders]]\nbase = [0.0, 0.0, 0.0]\naxis = [0.0, 0.0, 0.0]\nradius = 1.0",
+                "cylinder axis can't be zero",
+            ),
+            (
+                "[[cylinders]]\nbase = [0.0, 0.0, 0.0]\naxis = [0.0, 0.0, 1.0]\nradius = 0.0",
+                "radius must be positive, got 0",
+            ),
+        ];
+        for (shape, message) in shapes {
+            let src = format!("{SCENE}\n{shape}\nmaterial = \"glass\"\n");
+            let err = parse_scene(&src, Path::new("test.toml")).unwrap_err();
+            assert_eq!(err.message, message);
+            assert_eq!(err.location.unwrap().0, 27);
+        }
+    }
+
+    #[test]
+    fn camera_keyframes() {
+        let keys = r#"
+interpolation = "linear"
+keyframes = [
+    { time = 0.0, origin = [0.0, -2.5, 0.0], look_at = [0.0, 0.0, 0.0] },
+    { time = 2.0, origin = [2.0, -2.5, 0.0], look_at = [2.0, 0.0, 0.0] },
+]
+"#;
+        let src = SCENE.replace(
+            "look_at = [0.0, 0.0, 0.0]\n",
+            &format!("look_at = [0.0, 0.0, 0.0]{keys}"),
+        );
+        let (_, camera) = parse_scene(&src, Path::new("test.toml")).unwrap();
+
+        let frame = camera.at_interval(1.0, 1.5);
+        assert_eq!(frame.origin, [1.0, -2.5, 0.0]);
+        assert_eq!(frame.motion.unwrap().look_at, [1.5, 0.0, 0.0]);
+        assert!(camera.build(1.0).is_ok());
+
+        let src = src.replace("time = 2.0", "time = 0.0");
+        let err = parse_scene(&src, Path::new("test.toml")).unwrap_err();
+        assert_eq!(err.message, "camera keyframe times must increase");
+        assert_eq!(err.location.unwrap().0, 4);
+    }
+
+    #[test]
+    fn instance_keyframes() {
+        let dir = TempDir::new("instance_keyframes");
+        dir.write(
+            "tri.obj",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1\n",
+        );
+
+        let meshes = r#"
+[[meshes]]
+name = "tri"
+path = "tri.obj"
+material = "glass"
+
+[[instances]]
+mesh = "tri"
+keyframes = [
+    { time = 0.0, offset = [0.0, 0.0, 0.0] },
+    { time = 2.0, offset = [2.0, 0.0, 0.0] },
+]
+"#;
+        let src = SCENE.replace("[[triangles]]", &format!("{meshes}\n[[triangles]]"));
+        let (mut scene, _) = parse_scene(&src, &dir.join("test.toml")).unwrap();
+
+        assert_eq!(scene.animations.len(), 1);
+        let instance = scene.animations[0].instance;
+        scene.set_frame_interval(1.0, 1.5);
+        let posed = &scene.instances[instance];
+        assert_eq!(
+            posed.transform.point(Vec3::zeros()),
+            Vec3::new(1.0, 0.0, 0.0)
+        );
+        let motion = posed.motion.as_ref().unwrap();
+        assert_eq!(
+            motion.at(1.0).point(Vec3::zeros()),
+            Vec3::new(1.5, 0.0, 0.0)
+        );
+
+        // a still frame has no motion
+        scene.set_frame_interval(2.0, 2.0);
+        assert!(scene.instances[instance].motion.is_none());
+
+        let src = src.replace(
+            "mesh = \"tri\"\n",
+            "mesh = \"tri\"\nrotate = [0.0, 0.0, 90.0]\n",
+        );
+        let err = parse_scene(&src, &dir.join("test.toml")).unwrap_err();
+        assert_eq!(
+            err.message,
+            "keyframes can't be combined with scale, rotate, offset or matrix"
+        );
+    }
+
+    #[test]
+    fn spectrum_descriptions() {
+        let dir = TempDir::new("scene_spectra");
+        dir.write("red.csv", "nm,reflectance\n400,0.05\n600,0.1\n700,0.8\n");
+        image::RgbImage::from_pixel(1, 1, image::Rgb([255, 255, 255]))
+            .save(dir.join("white.png"))
+            .unwrap();
+
+        let materials = r#"
+[materials.red]
+type = "spectral_reflectance_distribution"
+reflectance = { csv = "red.csv" }
+
+[materials.grey]
+type = "spectral_mirror"
+reflectance = 0.5
+
+[materials.flint]
+type = "spectral_refract"
+ior = { cauchy = [1.6, 0.01] }
+
+[materials.sun]
+type = "spectral_power_distribution"
+irradiance = { blackbody = 5800.0 }
+
+[materials.sampled]
+type = "spectral_reflectance_distribution"
+reflectance = { wavelengths = [400.0, 700.0], values = [0.2, 0.4] }
+
+[materials.tinted]
+type = "textured"
+texture = "white.png"
+tint = [0.2, 0.3, 0.9]
+
+[materials.wall]
+type = "spectral_reflectance_distribution"
+reflectance = { srgb = [0.8, 0.3, 0.2] }
+
+[materials.warm]
+type = "spectral_power_distribution"
+irradiance = { rgb = [4.0, 2.0, 1.0] }
+"#;
+        let src = SCENE.replace("[[triangles]]", &format!("{materials}\n[[triangles]]"));
+        let (scene, _) = parse_scene(&src, &dir.join("test.toml")).unwrap();
+        // sorted by name after the cornell box
+        let Mat::SpectralRefract(flint) = &scene.materials[3] else {
+            panic!("expected flint glass");
+        };
+        assert!(flint.dispersive());
+        let Mat::SpectralReflectanceDistribution(red) = &scene.materials[6] else {
+            panic!("expected the csv reflectance");
+        };
+        assert!((red.albedo(650.0) - 0.45).abs() < 1e-6);
+        let Mat::SpectralReflectanceDistribution(sampled) = &scene.materials[7] else {
+            panic!("expected the sampled reflectance");
+        };
+        assert!((sampled.albedo(550.0) - 0.3).abs() < 1e-6);
+        assert!(matches!(scene.materials[9], Mat::Textured(_)));
+        let Mat::SpectralReflectanceDistribution(wall) = &scene.materials[10] else {
+            panic!("expected the srgb reflectance");
+        };
+        assert!(wall.albedo(650.0) > wall.albedo(450.0));
+        assert!(matches!(
+            scene.materials[11],
+            Mat::SpectralPowerDistribution(_)
+        ));
+
+        let bright = src.replace("values = [0.2, 0.4]", "values = [0.2, 1.4]");
+        let err = parse_scene(&bright, &dir.join("test.toml")).unwrap_err();
+        assert_eq!(
+            err.message,
+            "reflectance values must be in the range [0, 1)"
+        );
+        let coloured = src.replace("{ cauchy = [1.6, 0.01] }", "{ rgb = [1.0, 1.0, 1.0] }");
+        let err = parse_scene(&coloured, &dir.join("test.toml")).unwrap_err();
+        assert_eq!(err.message, "an ior can't be given as a colour");
+        let missing = src.replace("red.csv", "blue.csv");
+        let err = parse_scene(&missing, &dir.join("test.toml")).unwrap_err();
+        assert!(
+            err.message.starts_with("failed to load spectrum"),
+            "{}",
+            err.message
+        );
+    }
+}
@@ -0,0 +1,411 @@
+use crate::{prelude::*, primitive::Primitive};
+use bvh::aabb::{Aabb, Aabound};
+use derive_new::new;
+use std::f32::consts::{PI, TAU};
+use utility::{gamma, onb};
+
+type DVec3 = nalgebra::Vector3<f64>;
+
+// positive roots of a t^2 + 2 b t + c in increasing order, disc is b^2 - a c computed by
+// the caller in a way that avoids cancellation
+fn positive_roots(a: f64, b: f64, c: f64, disc: f64) -> [Option<f64>; 2] {
+    if disc < 0.0 || a == 0.0 {
+        return [None, None];
+    }
+    let root = disc.sqrt();
+    let q = if b < 0.0 { -b + root } else { -b - root };
+    if q == 0.0 {
+        return [None, None];
+    }
+
+    let (mut t0, mut t1) = (q / a, c / q);
+    if t0 > t1 {
+        std::mem::swap(&mut t0, &mut t1);
+    }
+    [t0, t1].map(|t| (t > 0.0).then_some(t))
+}
+
+// flat shapes would otherwise have zero extent along their normal
+fn padded(min: Vec3, max: Vec3) -> Aabb {
+    let pad = Vec3::repeat(1e-5) + gamma(3) * utility::max_vec3(&min.abs(), &max.abs());
+    Aabb::new(min - pad, max + pad)
+}
+
+// angle around the z axis of a local frame in [0, 2pi)
+fn phi(x: f32, y: f32) -> f32 {
+    let phi = y.atan2(x);
+    if phi < 0.0 {
+        phi + TAU
+    } else {
+        phi
+    }
+}
+
+// falls back to an arbitrary basis where the parameterisation is degenerate (e.g. poles)
+fn tangents(dpdu: Vec3, dpdv: Vec3, normal: Vec3) -> (Vec3, Vec3) {
+    let area = dpdu.cross(&dpdv).magnitude_squared();
+    if area == 0.0 || area.is_nan() {
+        onb(normal)
+    } else {
+        (dpdu, dpdv)
+    }
+}
+
+fn facing(mut normal: Vec3, ray: &Ray) -> (Vec3, bool) {
+    let out = normal.dot(&ray.dir) < 0.0;
+    if !out {
+        normal = -normal;
+    }
+    (normal, out)
+}
+
+#[derive(Debug, Clone, new)]
+pub struct Sphere {
+    pub centre: Vec3,
+    pub radius: f32,
+    pub mat: usize,
+}
+
+impl Aabound for Sphere {
+    fn aabb(&self) -> Aabb {
+        let r = Vec3::repeat(self.radius);
+        padded(self.centre - r, self.centre + r)
+    }
+}
+
+impl Primitive for Sphere {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let oc: DVec3 = (ray.origin - self.centre).cast();
+        let d: DVec3 = ray.dir.cast();
+        let r = self.radius as f64;
+
+        let a = d.norm_squared();
+        let b = d.dot(&oc);
+        let c = oc.norm_squared() - r * r;
+
+        // b^2 - a c rewritten as in pbrt-v4
+        let v = oc - (b / a) * d;
+        let len = v.norm();
+        let disc = a * (r - len) * (r + len);
+
+        let t = positive_roots(a, b, c, disc).into_iter().flatten().next()?;
+
+        // reproject onto the surface, leaving only the error of the reprojection
+        let local = oc + t * d;
+        let local: Vec3 = (local * (r / local.norm())).cast();
+
+        let pos = self.centre + local;
+        let err = gamma(5) * local.abs() + gamma(1) * pos.abs();
+
+        let normal = local / self.radius;
+
+        let phi = phi(local.x, local.y);
+        let theta = (local.z / self.radius).clamp(-1.0, 1.0).acos();
+        let uv = Vec2::new(phi / TAU, theta / PI);
+        let dpdu = Vec3::new(-TAU * local.y, TAU * local.x, 0.0);
+        let dpdv = PI
+            * Vec3::new(
+                local.z * phi.cos(),
+                local.z * phi.sin(),
+                -self.radius * theta.sin(),
+            );
+        let (dpdu, dpdv) = tangents(dpdu, dpdv, normal);
+
+        let (nor, out) = facing(normal, ray);
+
+        Some(Intersection::new(
+            t as f32, pos, err, nor, uv, dpdu, dpdv, out, self.mat,
+        ))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Disk {
+    pub centre: Vec3,
+    pub normal: Vec3,
+    pub radius: f32,
+    pub mat: usize,
+}
+
+impl Disk {
+    pub fn new(centre: Vec3, normal: Vec3, radius: f32, mat: usize) -> Self {
+        Self {
+            centre,
+            normal: normal.normalize(),
+            radius,
+            mat,
+        }
+    }
+}
+
+impl Aabound for Disk {
+    fn aabb(&self) -> Aabb {
+        let n = self.normal;
+        let extent = Vec3::new(1.0 - n.x * n.x, 1.0 - n.y * n.y, 1.0 - n.z * n.z)
+            .map(|v| self.radius * v.max(0.0).sqrt());
+        padded(self.centre - extent, self.centre + extent)
+    }
+}
+
+impl Primitive for Disk {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let (pos, t) = plane_intersect(ray, self.centre, self.normal)?;
+
+        if (pos - self.centre).magnitude_squared() > self.radius * self.radius {
+            return None;
+        }
+
+        let err = gamma(6) * (pos.abs() + self.centre.abs());
+
+        let (t1, t2) = onb(self.normal);
+        let local = pos - self.centre;
+        let (x, y) = (local.dot(&t1), local.dot(&t2));
+        let r_hit = (x * x + y * y).sqrt();
+
+        let uv = Vec2::new(phi(x, y) / TAU, 1.0 - r_hit / self.radius);
+        // u is undefined at the centre
+        let (dpdu, dpdv) = if r_hit == 0.0 {
+            (t1, t2)
+        } else {
+            let dpdu = TAU * (x * t2 - y * t1);
+            let dpdv = -(self.radius / r_hit) * local;
+            tangents(dpdu, dpdv, self.normal)
+        };
+
+        let (nor, out) = facing(self.normal, ray);
+
+        Some(Intersection::new(
+            t, pos, err, nor, uv, dpdu, dpdv, out, self.mat,
+        ))
+    }
+}
+
+// returns the hit point projected back onto the plane
+fn plane_intersect(ray: &Ray, point: Vec3, normal: Vec3) -> Option<(Vec3, f32)> {
+    let denom = normal.dot(&ray.dir);
+    if denom == 0.0 {
+        return None;
+    }
+
+    let t = normal.dot(&(point - ray.origin)) / denom;
+    if t <= 0.0 || !t.is_finite() {
+        return None;
+    }
+
+    let pos = ray.origin + t * ray.dir;
+    Some((pos - normal * normal.dot(&(pos - point)), t))
+}
+
+// parallelogram spanned by u & v from corner
+#[derive(Debug, Clone)]
+pub struct Quad {
+    pub corner: Vec3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub mat: usize,
+    normal: Vec3,
+    w: Vec3,
+}
+
+impl Quad {
+    pub fn new(corner: Vec3, u: Vec3, v: Vec3, mat: usize) -> Self {
+        let n = u.cross(&v);
+        Self {
+            corner,
+            u,
+            v,
+            mat,
+            normal: n.normalize(),
+            w: n / n.magnitude_squared(),
+        }
+    }
+}
+
+impl Aabound for Quad {
+    fn aabb(&self) -> Aabb {
+        let mut aabb = None;
+        for p in [
+            self.corner,
+            self.corner + self.u,
+            self.corner + self.v,
+            self.corner + self.u + self.v,
+        ] {
+            Aabb::extend_contains(&mut aabb, p);
+        }
+        let aabb = aabb.unwrap();
+        padded(aabb.min, aabb.max)
+    }
+}
+
+impl Primitive for Quad {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let (pos, t) = plane_intersect(ray, self.corner, self.normal)?;
+
+        let hp = pos - self.corner;
+        let alpha = self.w.dot(&hp.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&hp));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        let err = gamma(6) * (pos.abs() + self.corner.abs());
+        let (nor, out) = facing(self.normal, ray);
+
+        Some(Intersection::new(
+            t,
+            pos,
+            err,
+            nor,
+            Vec2::new(alpha, beta),
+            self.u,
+            self.v,
+            out,
+            self.mat,
+        ))
+    }
+}
+
+// open tube from base to base + axis
+#[derive(Debug, Clone)]
+pub struct Cylinder {
+    pub base: Vec3,
+    pub axis: Vec3,
+    pub radius: f32,
+    pub mat: usize,
+    frame: (Vec3, Vec3, Vec3),
+    height: f32,
+}
+
+impl Cylinder {
+    pub fn new(base: Vec3, axis: Vec3, radius: f32, mat: usize) -> Self {
+        let height = axis.magnitude();
+        let a = axis / height;
+        let (t1, t2) = onb(a);
+        Self {
+            base,
+            axis,
+            radius,
+            mat,
+            frame: (t1, t2, a),
+            height,
+        }
+    }
+}
+
+impl Aabound for Cylinder {
+    fn aabb(&self) -> Aabb {
+        let a = self.frame.2;
+        let extent = Vec3::new(1.0 - a.x * a.x, 1.0 - a.y * a.y, 1.0 - a.z * a.z)
+            .map(|v| self.radius * v.max(0.0).sqrt());
+        let top = self.base + self.axis;
+        padded(
+            utility::min_vec3(&self.base, &top) - extent,
+            utility::max_vec3(&self.base, &top) + extent,
+        )
+    }
+}
+
+impl Primitive for Cylinder {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let (t1, t2, a) = self.frame;
+        let to_local = |v: Vec3| -> DVec3 { Vec3::new(v.dot(&t1), v.dot(&t2), v.dot(&a)).cast() };
+
+        let o = to_local(ray.origin - self.base);
+        let d = to_local(ray.dir);
+        let r = self.radius as f64;
+
+        let a2 = d.x * d.x + d.y * d.y;
+        let b = d.x * o.x + d.y * o.y;
+        let c = o.x * o.x + o.y * o.y - r * r;
+
+        // b^2 - a c rewritten as in pbrt-v4
+        let (ox, oy) = (o.x - (b / a2) * d.x, o.y - (b / a2) * d.y);
+        let len = (ox * ox + oy * oy).sqrt();
+        let disc = a2 * (r - len) * (r + len);
+
+        let (t, local) = positive_roots(a2, b, c, disc)
+            .into_iter()
+            .flatten()
+            .map(|t| (t, o + t * d))
+            .find(|(_, p)| (0.0..=self.height as f64).contains(&p.z))?;
+
+        // reproject onto the surface
+        let scale = r / (local.x * local.x + local.y * local.y).sqrt();
+        let (x, y, z) = (
+            (local.x * scale) as f32,
+            (local.y * scale) as f32,
+            local.z as f32,
+        );
+
+        let (px, py, pz) = (x * t1, y * t2, z * a);
+        let pos = self.base + px + py + pz;
+        let err = gamma(5) * (self.base.abs() + px.abs() + py.abs() + pz.abs());
+
+        let normal = (px + py) / self.radius;
+
+        let uv = Vec2::new(phi(x, y) / TAU, z / self.height);
+        let dpdu = TAU * (x * t2 - y * t1);
+
+        let (nor, out) = facing(normal, ray);
+
+        Some(Intersection::new(
+            t as f32, pos, err, nor, uv, dpdu, self.axis, out, self.mat,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shape_intersections() {
+        let ray = Ray::new(Vec3::new(0.0, -5.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let hits = |p: &dyn Primitive| {
+            let int = p.intersect(&ray).unwrap();
+            assert!(int.out);
+            assert!(p.aabb().does_int(&ray));
+            (int.t, int.nor)
+        };
+
+        let sphere = Sphere::new(Vec3::zeros(), 1.0, 0);
+        let (t, nor) = hits(&sphere);
+        assert!((t - 4.0).abs() < 1e-6);
+        assert!((nor - Vec3::new(0.0, -1.0, 0.0)).magnitude() < 1e-6);
+
+        let disk = Disk::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.5, 0);
+        let (t, nor) = hits(&disk);
+        assert!((t - 6.0).abs() < 1e-6);
+        assert_eq!(nor, Vec3::new(0.0, -1.0, 0.0));
+        // hit at the exact centre
+        let int = disk.intersect(&ray).unwrap();
+        assert!(int.dpdu.cross(&int.dpdv).magnitude() > 0.0);
+
+        let quad = Quad::new(
+            Vec3::new(-1.0, 2.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            0,
+        );
+        let (t, _) = hits(&quad);
+        assert!((t - 7.0).abs() < 1e-6);
+        let int = quad.intersect(&ray).unwrap();
+        assert!((int.uv - Vec2::new(0.5, 0.5)).magnitude() < 1e-6);
+        assert_eq!(int.dpdu, Vec3::new(2.0, 0.0, 0.0));
+
+        let cylinder = Cylinder::new(Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 2.0), 2.0, 0);
+        let (t, nor) = hits(&cylinder);
+        assert!((t - 3.0).abs() < 1e-5);
+        assert!((nor - Vec3::new(0.0, -1.0, 0.0)).magnitude() < 1e-5);
+        let int = cylinder.intersect(&ray).unwrap();
+        assert!((int.uv.y - 0.5).abs() < 1e-5);
+        assert!(int.dpdu.cross(&int.dpdv).dot(&int.nor) > 0.0);
+
+        // from inside the sphere & missing the disk
+        let inside = Ray::new(Vec3::zeros(), Vec3::new(1.0, 0.0, 0.0));
+        let int = sphere.intersect(&inside).unwrap();
+        assert!(!int.out);
+        assert!((int.pos - Vec3::new(1.0, 0.0, 0.0)).magnitude() < 1e-6);
+        assert!(disk.intersect(&inside).is_none());
+    }
+}
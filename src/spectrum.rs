@@ -0,0 +1,221 @@
+use crate::{
+    load_error::LoadError,
+    material::{MAX_WAVELENGTH, MIN_WAVELENGTH, WAVELENGTH_RANGE},
+    rgb_spectrum::SigmoidPolynomial,
+};
+use std::path::Path;
+
+// a function of wavelength in nm
+#[derive(Debug, Clone)]
+pub enum Spectrum {
+    Constant(f32),
+    Tabulated(Tabulated),
+    // planck's law in kelvin, scaled to a peak of 1
+    Blackbody(f32),
+    // cauchy's equation a + b / wl^2 with wl in micrometres, for dispersive iors
+    Cauchy { a: f32, b: f32 },
+    // upsampled from rgb, always in (0, 1)
+    Sigmoid(SigmoidPolynomial),
+}
+
+impl Spectrum {
+    // values evenly spaced from 380nm to 750nm
+    pub fn from_bins(values: &[f32]) -> Self {
+        match values {
+            [] => Self::Constant(0.0),
+            [value] => Self::Constant(*value),
+            _ => {
+                let step = WAVELENGTH_RANGE / (values.len() - 1) as f32;
+                let samples = values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &v)| (MIN_WAVELENGTH + i as f32 * step, v))
+                    .collect();
+                Self::Tabulated(Tabulated::new(samples).unwrap())
+            }
+        }
+    }
+
+    // rows of wavelength in nm & value separated by commas or whitespace, blank lines & #
+    // comments are skipped, as is the first row if it isn't numeric
+    pub fn parse_csv(src: &str) -> Result<Self, LoadError> {
+        let mut samples = Vec::new();
+        let mut first = true;
+        for (i, line) in src.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let header = std::mem::replace(&mut first, false);
+            let row = line
+                .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+                .filter(|v| !v.is_empty())
+                .map(str::parse::<f32>)
+                .collect::<Result<Vec<_>, _>>();
+            match row {
+                Ok(row) if row.len() == 2 => samples.push((row[0], row[1])),
+                Ok(row) => {
+                    return Err(LoadError::Parse(format!(
+                        "line {}: expected 2 columns, got {}",
+                        i + 1,
+                        row.len()
+                    )))
+                }
+                Err(_) if header => continue,
+                Err(e) => return Err(LoadError::Parse(format!("line {}: {e}", i + 1))),
+            }
+        }
+        if samples.is_empty() {
+            return Err(LoadError::Parse("no spectrum samples".into()));
+        }
+        Tabulated::new(samples)
+            .map(Self::Tabulated)
+            .ok_or_else(|| LoadError::Parse("wavelengths must increase".into()))
+    }
+
+    pub fn open_csv(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        Self::parse_csv(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn eval(&self, wavelength: f32) -> f32 {
+        match self {
+            Self::Constant(value) => *value,
+            Self::Tabulated(table) => table.eval(wavelength),
+            Self::Blackbody(temperature) => {
+                // wien's displacement law gives the peak
+                let peak = 2.897_772e6 / temperature;
+                planck(wavelength, *temperature) / planck(peak, *temperature)
+            }
+            Self::Cauchy { a, b } => {
+                let wl = 1e-3 * wavelength;
+                a + b / (wl * wl)
+            }
+            Self::Sigmoid(sigmoid) => sigmoid.eval(wavelength),
+        }
+    }
+
+    // smallest & largest values over the rendered wavelengths
+    pub fn bounds(&self) -> (f32, f32) {
+        if let Self::Sigmoid(sigmoid) = self {
+            return sigmoid.bounds();
+        }
+        let mut wavelengths = vec![MIN_WAVELENGTH, MAX_WAVELENGTH];
+        match self {
+            Self::Tabulated(table) => wavelengths.extend(
+                table
+                    .wavelengths
+                    .iter()
+                    .filter(|wl| (MIN_WAVELENGTH..=MAX_WAVELENGTH).contains(*wl)),
+            ),
+            Self::Blackbody(temperature) => {
+                wavelengths.push((2.897_772e6 / temperature).clamp(MIN_WAVELENGTH, MAX_WAVELENGTH))
+            }
+            Self::Constant(_) | Self::Cauchy { .. } | Self::Sigmoid(_) => (),
+        }
+        wavelengths
+            .into_iter()
+            .map(|wl| self.eval(wl))
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), v| {
+                (min.min(v), max.max(v))
+            })
+    }
+
+    pub fn is_constant(&self) -> bool {
+        match self {
+            Self::Constant(_) => true,
+            Self::Tabulated(table) => table.values.iter().all(|&v| v == table.values[0]),
+            Self::Blackbody(_) => false,
+            Self::Cauchy { b, .. } => *b == 0.0,
+            Self::Sigmoid(sigmoid) => sigmoid.is_constant(),
+        }
+    }
+}
+
+// irregularly spaced samples, linearly interpolated & held past the first & last
+#[derive(Debug, Clone)]
+pub struct Tabulated {
+    wavelengths: Vec<f32>,
+    values: Vec<f32>,
+}
+
+impl Tabulated {
+    // None without samples or when the wavelengths aren't strictly increasing
+    pub fn new(samples: Vec<(f32, f32)>) -> Option<Self> {
+        if samples.is_empty() || samples.windows(2).any(|w| w[0].0 >= w[1].0) {
+            return None;
+        }
+        let (wavelengths, values) = samples.into_iter().unzip();
+        Some(Self {
+            wavelengths,
+            values,
+        })
+    }
+
+    pub fn eval(&self, wavelength: f32) -> f32 {
+        let last = self.wavelengths.len() - 1;
+        if wavelength <= self.wavelengths[0] {
+            return self.values[0];
+        }
+        if wavelength >= self.wavelengths[last] {
+            return self.values[last];
+        }
+        let i = self.wavelengths.partition_point(|&wl| wl <= wavelength) - 1;
+        let t =
+            (wavelength - self.wavelengths[i]) / (self.wavelengths[i + 1] - self.wavelengths[i]);
+        self.values[i] * (1.0 - t) + self.values[i + 1] * t
+    }
+}
+
+// spectral radiance of a black body, wavelength in nm & temperature in kelvin
+fn planck(wavelength: f32, temperature: f32) -> f32 {
+    const C: f64 = 299_792_458.0;
+    const H: f64 = 6.626_070_15e-34;
+    const KB: f64 = 1.380_649e-23;
+    let wl = wavelength as f64 * 1e-9;
+    let radiance =
+        2.0 * H * C * C / (wl.powi(5) * ((H * C / (wl * KB * temperature as f64)).exp() - 1.0));
+    radiance as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spectra() {
+        let table = Spectrum::parse_csv(
+            "wavelength,value\n# measured\n400, 0.2\n500,0.6\n\n650 1.0 # red\n",
+        )
+        .unwrap();
+        assert_eq!(table.eval(300.0), 0.2);
+        assert!((table.eval(450.0) - 0.4).abs() < 1e-6);
+        assert!((table.eval(600.0) - 0.8666667).abs() < 1e-6);
+        assert_eq!(table.eval(700.0), 1.0);
+        assert_eq!(table.bounds(), (0.2, 1.0));
+        assert!(!table.is_constant());
+
+        assert!(Spectrum::parse_csv("400,1\n300,2").is_err());
+        assert!(Spectrum::parse_csv("400,1\n500,2,3").is_err());
+        assert!(Spectrum::parse_csv("400,1\n500,x").is_err());
+        assert!(Spectrum::parse_csv("wavelength,value").is_err());
+        // only a header is skipped, not a typo further down
+        assert!(Spectrum::parse_csv("wavelength,value\n4OO,1\n500,2").is_err());
+        assert!(Spectrum::parse_csv("400,1\n500,2").is_ok());
+
+        // bins land on their wavelengths
+        let bins = Spectrum::from_bins(&[0.0, 1.0, 0.5]);
+        assert_eq!(bins.eval(MIN_WAVELENGTH + 0.5 * WAVELENGTH_RANGE), 1.0);
+        assert_eq!(bins.eval(MAX_WAVELENGTH), 0.5);
+
+        // a 5000K black body peaks near 580nm
+        let sun = Spectrum::Blackbody(5000.0);
+        assert!((sun.eval(579.55) - 1.0).abs() < 1e-4);
+        assert!(sun.eval(400.0) < sun.eval(500.0));
+        assert!((sun.bounds().1 - 1.0).abs() < 1e-4);
+
+        let glass = Spectrum::Cauchy { a: 1.5, b: 0.004 };
+        assert!(glass.eval(400.0) > glass.eval(700.0));
+        assert_eq!(glass.bounds(), (glass.eval(750.0), glass.eval(380.0)));
+        assert!(Spectrum::Cauchy { a: 1.5, b: 0.0 }.is_constant());
+    }
+}
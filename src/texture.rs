@@ -0,0 +1,89 @@
+use crate::{colour::srgb_to_linear, prelude::*};
+use image::{ColorType, ImageResult};
+use std::path::Path;
+
+// linear rgb image, repeats outside of [0, 1]
+#[derive(Debug)]
+pub struct Texture {
+    width: usize,
+    height: usize,
+    texels: Vec<Vec3>,
+}
+
+impl Texture {
+    pub fn new(width: usize, height: usize, texels: Vec<Vec3>) -> Self {
+        assert_eq!(width * height, texels.len());
+        assert!(
+            !texels.is_empty(),
+            "texture must contain at least one texel"
+        );
+        Self {
+            width,
+            height,
+            texels,
+        }
+    }
+
+    // 8 and 16 bit images are assumed to be sRGB encoded
+    pub fn open(path: impl AsRef<Path>) -> ImageResult<Self> {
+        let image = image::open(path)?;
+        let linear = matches!(image.color(), ColorType::Rgb32F | ColorType::Rgba32F);
+        let image = image.into_rgb32f();
+
+        let texels = image
+            .pixels()
+            .map(|p| {
+                let rgb = Vec3::from(p.0);
+                if linear {
+                    rgb
+                } else {
+                    rgb.map(srgb_to_linear)
+                }
+            })
+            .collect();
+
+        Ok(Self::new(
+            image.width() as usize,
+            image.height() as usize,
+            texels,
+        ))
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    // row major from the top
+    pub fn texels(&self) -> &[Vec3] {
+        &self.texels
+    }
+
+    fn texel_index(&self, x: isize, y: isize) -> usize {
+        let x = x.rem_euclid(self.width as isize) as usize;
+        let y = y.rem_euclid(self.height as isize) as usize;
+        y * self.width + x
+    }
+
+    // indices of the texels around uv & their bilinear weights, v = 0 is the bottom row
+    pub fn bilinear(&self, uv: Vec2) -> [(usize, f32); 4] {
+        let x = uv.x * self.width as f32 - 0.5;
+        let y = (1.0 - uv.y) * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as isize, y0 as isize);
+
+        [
+            (self.texel_index(x0, y0), (1.0 - tx) * (1.0 - ty)),
+            (self.texel_index(x0 + 1, y0), tx * (1.0 - ty)),
+            (self.texel_index(x0, y0 + 1), (1.0 - tx) * ty),
+            (self.texel_index(x0 + 1, y0 + 1), tx * ty),
+        ]
+    }
+
+    pub fn lookup(&self, uv: Vec2) -> Vec3 {
+        self.bilinear(uv)
+            .into_iter()
+            .map(|(i, w)| self.texels[i] * w)
+            .sum()
+    }
+}
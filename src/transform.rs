@@ -0,0 +1,315 @@
+use crate::prelude::*;
+use bvh::aabb::Aabb;
+use nalgebra::{Matrix3, UnitQuaternion};
+use std::ops::Mul;
+use utility::gamma;
+
+pub type Mat4 = nalgebra::Matrix4<f32>;
+
+// number of times the motion is sampled when bounding it
+const MOTION_STEPS: usize = 32;
+
+// affine transform with its inverse kept alongside
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transform {
+    m: Mat4,
+    inv: Mat4,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Transform {
+    pub fn new(m: Mat4) -> Option<Self> {
+        let inv = m.try_inverse()?;
+        Some(Self { m, inv })
+    }
+
+    pub fn identity() -> Self {
+        Self {
+            m: Mat4::identity(),
+            inv: Mat4::identity(),
+        }
+    }
+
+    pub fn translate(offset: Vec3) -> Self {
+        Self {
+            m: Mat4::new_translation(&offset),
+            inv: Mat4::new_translation(&-offset),
+        }
+    }
+
+    pub fn scale(scale: Vec3) -> Option<Self> {
+        Self::new(Mat4::new_nonuniform_scaling(&scale))
+    }
+
+    // counter-clockwise around axis
+    pub fn rotate(axis: Vec3, degrees: f32) -> Self {
+        let m = Mat4::from_axis_angle(&nalgebra::Unit::new_normalize(axis), degrees.to_radians());
+        Self {
+            m,
+            inv: m.transpose(),
+        }
+    }
+
+    // scaled, rotated in degrees around x, y then z & then offset
+    pub fn from_parts(scale: Vec3, rotate: Vec3, offset: Vec3) -> Option<Self> {
+        Some(
+            Self::translate(offset)
+                * Self::rotate(Vec3::z(), rotate.z)
+                * Self::rotate(Vec3::y(), rotate.y)
+                * Self::rotate(Vec3::x(), rotate.x)
+                * Self::scale(scale)?,
+        )
+    }
+
+    pub fn matrix(&self) -> &Mat4 {
+        &self.m
+    }
+
+    pub fn inverse(&self) -> Self {
+        Self {
+            m: self.inv,
+            inv: self.m,
+        }
+    }
+
+    pub fn point(&self, p: Vec3) -> Vec3 {
+        self.m.transform_point(&p.into()).coords
+    }
+
+    pub fn vector(&self, v: Vec3) -> Vec3 {
+        self.m.transform_vector(&v)
+    }
+
+    pub fn normal(&self, n: Vec3) -> Vec3 {
+        self.inv.fixed_view::<3, 3>(0, 0).tr_mul(&n)
+    }
+
+    // transforms a point along with its absolute error bound
+    pub fn point_with_error(&self, p: Vec3, err: Vec3) -> (Vec3, Vec3) {
+        let abs = self.m.fixed_view::<3, 3>(0, 0).abs();
+        let translation = self.m.fixed_view::<3, 1>(0, 3).abs();
+
+        let err = (gamma(3) + 1.0) * (abs * err) + gamma(3) * (abs * p.abs() + translation);
+
+        (self.point(p), err)
+    }
+
+    // keeps the direction unnormalised so t values match in both spaces
+    pub fn ray(&self, ray: &Ray) -> Ray {
+        Ray::new_with_time(self.point(ray.origin), self.vector(ray.dir), ray.time)
+    }
+
+    pub fn inverse_ray(&self, ray: &Ray) -> Ray {
+        Ray::new_with_time(
+            self.inv.transform_point(&ray.origin.into()).coords,
+            self.inv.transform_vector(&ray.dir),
+            ray.time,
+        )
+    }
+
+    pub fn aabb(&self, aabb: &Aabb) -> Aabb {
+        let mut out = None;
+        for i in 0..8 {
+            let corner = Vec3::new(
+                if i & 1 == 0 { aabb.min.x } else { aabb.max.x },
+                if i & 2 == 0 { aabb.min.y } else { aabb.max.y },
+                if i & 4 == 0 { aabb.min.z } else { aabb.max.z },
+            );
+            Aabb::extend_contains(&mut out, self.point(corner));
+        }
+        let out = out.unwrap();
+
+        // pad for the rounding error of transforming the corners
+        let pad = gamma(3) * utility::max_vec3(&out.min.abs(), &out.max.abs());
+        Aabb::new(out.min - pad, out.max + pad)
+    }
+}
+
+impl Mul for Transform {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self {
+            m: self.m * rhs.m,
+            inv: rhs.inv * self.inv,
+        }
+    }
+}
+
+// moves from start at time 0 to end at time 1, translation, rotation & scale are
+// interpolated separately so rotations don't shear
+#[derive(Debug, Clone)]
+pub struct AnimatedTransform {
+    start: Transform,
+    end: Transform,
+    translation: [Vec3; 2],
+    rotation: [UnitQuaternion<f32>; 2],
+    scale: [Matrix3<f32>; 2],
+}
+
+impl AnimatedTransform {
+    // None when only one of start & end is mirrored, the scale would pass through zero
+    pub fn new(start: Transform, end: Transform) -> Option<Self> {
+        let (t0, r0, s0) = decompose(start.matrix());
+        let (t1, mut r1, s1) = decompose(end.matrix());
+        if (s0.determinant() < 0.0) != (s1.determinant() < 0.0) {
+            return None;
+        }
+        // take the shorter way around
+        if r0.coords.dot(&r1.coords) < 0.0 {
+            r1 = UnitQuaternion::new_unchecked(-r1.into_inner());
+        }
+        Some(Self {
+            start,
+            end,
+            translation: [t0, t1],
+            rotation: [r0, r1],
+            scale: [s0, s1],
+        })
+    }
+
+    pub fn start(&self) -> &Transform {
+        &self.start
+    }
+
+    pub fn at(&self, time: f32) -> Transform {
+        if time <= 0.0 {
+            return self.start.clone();
+        }
+        if time >= 1.0 {
+            return self.end.clone();
+        }
+
+        let translation = self.translation[0].lerp(&self.translation[1], time);
+        let rotation = self.rotation[0].slerp(&self.rotation[1], time);
+        let scale = self.scale[0] * (1.0 - time) + self.scale[1] * time;
+
+        let mut m = (rotation.to_rotation_matrix().into_inner() * scale).to_homogeneous();
+        m.fixed_view_mut::<3, 1>(0, 3).copy_from(&translation);
+        // the scales are both positive or both negative definite, so every blend is invertible
+        Transform::new(m).unwrap()
+    }
+
+    // covers the whole motion, padded for rotation between the sampled times
+    pub fn aabb(&self, aabb: &Aabb) -> Aabb {
+        let mut out = self.start.aabb(aabb);
+        for i in 1..=MOTION_STEPS {
+            let b = self.at(i as f32 / MOTION_STEPS as f32).aabb(aabb);
+            out = Aabb::new(
+                utility::min_vec3(&out.min, &b.min),
+                utility::max_vec3(&out.max, &b.max),
+            );
+        }
+
+        // a point r from the rotation centre strays r (1 - cos(angle / 2)) from the chord, r is
+        // largest at a corner & the blended scales never stretch further than both ends
+        let angle = self.rotation[0].angle_to(&self.rotation[1]) / MOTION_STEPS as f32;
+        let radius = (0..8)
+            .flat_map(|i| {
+                let corner = Vec3::new(
+                    if i & 1 == 0 { aabb.min.x } else { aabb.max.x },
+                    if i & 2 == 0 { aabb.min.y } else { aabb.max.y },
+                    if i & 4 == 0 { aabb.min.z } else { aabb.max.z },
+                );
+                self.scale.map(|s| (s * corner).magnitude())
+            })
+            .fold(0.0, f32::max);
+        let pad = Vec3::repeat(radius * (1.0 - (0.5 * angle).cos()));
+        Aabb::new(out.min - pad, out.max + pad)
+    }
+}
+
+// translation, rotation & the remaining scale (which may include shear)
+fn decompose(m: &Mat4) -> (Vec3, UnitQuaternion<f32>, Matrix3<f32>) {
+    let translation = m.fixed_view::<3, 1>(0, 3).into_owned();
+    let linear = m.fixed_view::<3, 3>(0, 0).into_owned();
+
+    // polar decomposition, averaging with the inverse transpose converges to the rotation
+    let mut rotation = linear;
+    for _ in 0..100 {
+        let Some(inv) = rotation.try_inverse() else {
+            break;
+        };
+        let next = 0.5 * (rotation + inv.transpose());
+        let done = (next - rotation).abs().max() < 1e-6;
+        rotation = next;
+        if done {
+            break;
+        }
+    }
+    // mirroring is left in the scale
+    if rotation.determinant() < 0.0 {
+        rotation = -rotation;
+    }
+    let scale = rotation.transpose() * linear;
+
+    let rotation = UnitQuaternion::from_matrix(&rotation);
+    (translation, rotation, scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compose_and_invert() {
+        let t = Transform::translate(Vec3::new(1.0, 2.0, 3.0))
+            * Transform::rotate(Vec3::new(0.0, 0.0, 1.0), 90.0)
+            * Transform::scale(Vec3::repeat(2.0)).unwrap();
+
+        let p = t.point(Vec3::new(1.0, 0.0, 0.0));
+        assert!((p - Vec3::new(1.0, 4.0, 3.0)).magnitude() < 1e-5);
+
+        let back = t.inverse().point(p);
+        assert!((back - Vec3::new(1.0, 0.0, 0.0)).magnitude() < 1e-5);
+
+        let n = t.normal(Vec3::new(1.0, 0.0, 0.0)).normalize();
+        assert!((n - Vec3::new(0.0, 1.0, 0.0)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn animated_transform() {
+        let start = Transform::translate(Vec3::new(1.0, 0.0, 0.0));
+        let end = Transform::translate(Vec3::new(1.0, 2.0, 0.0))
+            * Transform::rotate(Vec3::new(0.0, 0.0, 1.0), 90.0)
+            * Transform::scale(Vec3::repeat(3.0)).unwrap();
+        let animated = AnimatedTransform::new(start, end.clone()).unwrap();
+
+        let p = Vec3::new(1.0, 0.0, 0.0);
+        assert!((animated.at(0.0).point(p) - Vec3::new(2.0, 0.0, 0.0)).magnitude() < 1e-5);
+        assert!((animated.at(1.0).point(p) - end.point(p)).magnitude() < 1e-5);
+
+        // halfway is 45 degrees around with twice the scale, not a blend of the matrices
+        let expected = Vec3::new(1.0, 1.0, 0.0) + Vec3::new(1.0, 1.0, 0.0).normalize() * 2.0;
+        assert!((animated.at(0.5).point(p) - expected).magnitude() < 1e-4);
+
+        let unit = Aabb::new(-Vec3::repeat(1.0), Vec3::repeat(1.0));
+        let bounds = animated.aabb(&unit);
+        for i in 0..=100 {
+            let b = animated.at(i as f32 / 100.0).aabb(&unit);
+            assert!(b.min.iter().zip(&bounds.min).all(|(a, b)| a >= b));
+            assert!(b.max.iter().zip(&bounds.max).all(|(a, b)| a <= b));
+        }
+
+        // far from the rotation centre, the corner passes its largest x halfway between two of
+        // the sampled times
+        let spin = AnimatedTransform::new(Transform::identity(), Transform::rotate(Vec3::z(), 5.0))
+            .unwrap();
+        let corner = Vec3::new(100.0, -0.409, 0.0);
+        let far = Aabb::new(Vec3::new(99.0, -0.409, -0.5), Vec3::new(100.0, 0.5, 0.5));
+        let bounds = spin.aabb(&far);
+        for i in 0..=10_000 {
+            let p = spin.at(i as f32 / 10_000.0).point(corner);
+            assert!(p.iter().zip(&bounds.min).all(|(a, b)| a >= b));
+            assert!(p.iter().zip(&bounds.max).all(|(a, b)| a <= b));
+        }
+
+        let mirrored = Transform::scale(Vec3::new(-1.0, 1.0, 1.0)).unwrap();
+        assert!(AnimatedTransform::new(Transform::identity(), mirrored).is_none());
+    }
+}
@@ -1,19 +1,26 @@
-use crate::prelude::*;
-use bvh::aabb::{Aabb, Aabound};
+use crate::{mesh::Mesh, prelude::*};
+use bvh::aabb::Aabb;
 use derive_new::new;
 
 #[derive(Debug, new, PartialEq)]
 pub struct Triangle {
-    pos: [usize; 3],
-    nor: [usize; 3],
+    pub pos: [usize; 3],
+    pub nor: [usize; 3],
     pub mat: usize,
+    #[new(default)]
+    pub uv: Option<[usize; 3]>,
 }
 
-impl Aabound for Triangle {
-    fn aabb(&self) -> Aabb {
-        let a = unsafe { VERTICES[self.pos[0]] };
-        let b = unsafe { VERTICES[self.pos[1]] };
-        let c = unsafe { VERTICES[self.pos[2]] };
+impl Triangle {
+    pub fn with_uv(mut self, uv: [usize; 3]) -> Self {
+        self.uv = Some(uv);
+        self
+    }
+
+    pub fn aabb(&self, vertices: &[Vec3]) -> Aabb {
+        let a = vertices[self.pos[0]];
+        let b = vertices[self.pos[1]];
+        let c = vertices[self.pos[2]];
 
         let min_x = a.x.min(b.x).min(c.x);
         let min_y = a.y.min(b.y).min(c.y);
@@ -52,10 +59,10 @@ pub fn gamma(n: u32) -> f32 {
 }
 
 impl Triangle {
-    pub fn intersect(&self, ray: &Ray) -> Option<Intersection> {
-        let v0 = unsafe { VERTICES[self.pos[0]] };
-        let v1 = unsafe { VERTICES[self.pos[1]] };
-        let v2 = unsafe { VERTICES[self.pos[2]] };
+    pub fn intersect(&self, ray: &Ray, mesh: &Mesh) -> Option<Intersection> {
+        let v0 = mesh.vertices[self.pos[0]];
+        let v1 = mesh.vertices[self.pos[1]];
+        let v2 = mesh.vertices[self.pos[2]];
 
         let ro: Vec3 = Vec3::new(ray.origin.x, ray.origin.y, ray.origin.z);
 
@@ -133,9 +140,9 @@ impl Triangle {
 
         let t = inv_det * t_scaled;
 
-        let n0 = unsafe { NORMALS[self.nor[0]] };
-        let n1 = unsafe { NORMALS[self.nor[1]] };
-        let n2 = unsafe { NORMALS[self.nor[2]] };
+        let n0 = mesh.normals[self.nor[0]];
+        let n1 = mesh.normals[self.nor[1]];
+        let n2 = mesh.normals[self.nor[2]];
 
         let mut normal = b0 * n0 + b1 * n1 + b2 * n2;
 
@@ -153,13 +160,69 @@ impl Triangle {
 
         let point = b0 * v0 + b1 * v1 + b2 * v2;
 
-        Some(Intersection::new(
-            t,
-            point,
-            point_error,
-            normal,
-            out,
-            self.mat,
-        ))
+        // same default parameterisation as pbrt when the mesh has none
+        let [uv0, uv1, uv2] = match self.uv {
+            Some(uv) => uv.map(|i| mesh.uvs[i]),
+            None => [
+                Vec2::new(0.0, 0.0),
+                Vec2::new(1.0, 0.0),
+                Vec2::new(1.0, 1.0),
+            ],
+        };
+        let uv = b0 * uv0 + b1 * uv1 + b2 * uv2;
+
+        let (duv02, duv12) = (uv0 - uv2, uv1 - uv2);
+        let (dp02, dp12) = (v0 - v2, v1 - v2);
+        let det = duv02.x * duv12.y - duv02.y * duv12.x;
+
+        let mut dp = None;
+        if det.abs() >= 1e-9 {
+            let inv_det = 1.0 / det;
+            let dpdu = (duv12.y * dp02 - duv02.y * dp12) * inv_det;
+            let dpdv = (duv02.x * dp12 - duv12.x * dp02) * inv_det;
+            if dpdu.cross(&dpdv).magnitude_squared() != 0.0 {
+                dp = Some((dpdu, dpdv));
+            }
+        }
+        // degenerate uvs, any basis perpendicular to the geometric normal will do
+        let (dpdu, dpdv) = dp.unwrap_or_else(|| utility::onb(dp02.cross(&dp12).normalize()));
+
+        let mut int =
+            Intersection::new(t, point, point_error, normal, uv, dpdu, dpdv, out, self.mat);
+        if !mesh.reflectances.is_empty() {
+            let [r0, r1, r2] = self.pos.map(|i| mesh.reflectances[i]);
+            int.colour = Some([(r0, b0), (r1, b1), (r2, b2)]);
+        }
+
+        Some(int)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn triangle_uvs() {
+        let vertices = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+        ];
+        // listed out of order so the indices matter
+        let uvs = vec![
+            Vec2::new(0.0, 1.0),
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 0.0),
+        ];
+        let tri = Triangle::new([0, 1, 2], [0; 3], 0).with_uv([1, 2, 0]);
+        let mesh = Mesh::new_with_uvs(vertices, vec![Vec3::z()], uvs, vec![tri]);
+
+        let ray = Ray::new(Vec3::new(0.5, 1.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let int = mesh.triangles[0].intersect(&ray, &mesh).unwrap();
+        assert!((int.t - 1.0).abs() < 1e-6);
+        assert!((int.uv - Vec2::new(0.25, 0.5)).magnitude() < 1e-6);
+        assert!((int.dpdu - Vec3::new(2.0, 0.0, 0.0)).magnitude() < 1e-6);
+        assert!((int.dpdv - Vec3::new(0.0, 2.0, 0.0)).magnitude() < 1e-6);
     }
 }